@@ -1,46 +1,79 @@
-use csv_validate::Validator;
-use csv_validate::{process_input, validator_a, validator_b};
-use divan::black_box;
-use divan::Bencher;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, BufWriter, Write};
-use std::time::Instant;
+use csv_validator_core::config::CommonConfig;
+use csv_validator_core::engine::{process_input, process_mapped, thread_pool, Limits, Sinks};
+use csv_validator_core::issue_log::IssueStorage;
+use csv_validator_core::validators::Validator;
+use csv_validator_core::validators::issue::{ErrorPolicy, Policy};
+use csv_validator_core::validators::line_validators::{FieldCount, FieldCountConfig, IllegalChars, IllegalCharsConfig};
+use divan::{black_box, Bencher};
+use std::io;
+use std::sync::LazyLock;
 
 fn main() {
-    let filename = "../../tools/output.csv";
-    let outfilename = "../../tools/test_output.csv";
-
-    // Optional: Measure memory before (requires jemalloc)
-    #[cfg(feature = "jemalloc")]
-    let mem_before = jemalloc_ctl::stats::allocated::read().unwrap();
+    divan::main();
+}
 
-    let start = Instant::now();
+const ROWS: usize = 200_000;
 
-    let file = File::open(filename).expect("Unable to open input file");
-    let reader: Box<dyn BufRead> = Box::new(BufReader::new(file));
-    let output_file = File::create(outfilename).expect("Unable to create output file");
-    let mut writer: Box<dyn Write> = Box::new(BufWriter::new(output_file));
+/// Rows like the ones of `tools/csv_generator.py`, with an illegal character in every tenth row.
+static INPUT: LazyLock<String> = LazyLock::new(|| {
+    let mut input = String::from("string_1;datetime_2;int_3;float_4;longtext_5\n");
+    for i in 0..ROWS {
+        let text = if i % 10 == 0 { "lorem ipsum! dolor sit amet" } else { "lorem ipsum dolor sit amet" };
+        input.push_str(&format!("\"row {}\";2024-01-01 12:00:00;{};{}.25;\"{}\"\n", i, i, i % 1000, text));
+    }
+    input
+});
 
-    let mut available_validators: HashMap<&str, Box<Validator>> = HashMap::new();
-    available_validators.insert("validator_a", Box::new(validator_a));
-    available_validators.insert("validator_b", Box::new(validator_b));
+fn validators(fix: bool) -> Vec<Box<dyn Validator>> {
+    let common = CommonConfig { separator: Some(";".to_string()), has_header: Some(true), ..CommonConfig::default() };
+    vec![
+        Box::new(IllegalChars::new(IllegalCharsConfig {
+            illegal_chars: vec!["!".to_string(), "?".to_string(), "@".to_string()],
+            replace_with: vec!["_".to_string(), ".".to_string(), "-".to_string()],
+            common: common.clone(),
+            policy: Policy { on_error: if fix { ErrorPolicy::Fix } else { ErrorPolicy::Drop }, ..Policy::default() },
+        })),
+        Box::new(FieldCount::new(FieldCountConfig { expected: 5, common, policy: Policy::default() })),
+    ]
+}
 
-    let mut validators = Vec::new();
-    validators.push((
-        "validator_a".to_string(),
-        available_validators.get("validator_a").unwrap(),
-        true,
-    ));
+fn run(bencher: Bencher, fix: bool, mapped: bool, threads: usize) {
+    let common = CommonConfig { separator: Some(";".to_string()), has_header: Some(true), ..CommonConfig::default() };
+    let pool = thread_pool(threads).expect("Failed to build the thread pool");
+    bencher
+        .with_inputs(|| validators(fix))
+        .counter(divan::counter::BytesCount::of_str(INPUT.as_str()))
+        .bench_local_values(|mut validators| {
+            let mut sinks = Sinks {
+                output: Box::new(io::sink()),
+                rejects: Some(Box::new(io::sink())),
+                reject_reason: false,
+                issues: IssueStorage::default(),
+            };
+            let input = black_box(INPUT.as_bytes());
+            // small batches, so the stages of the pipeline overlap
+            let mem_limit = 8 * 1024 * 1024;
+            let limits = Limits::default();
+            let summary = if mapped {
+                process_mapped(input, &mut validators, &mut sinks, &common, &limits, mem_limit, &pool)
+            } else {
+                process_input(input, &mut validators, &mut sinks, &common, &limits, mem_limit, &pool)
+            };
+            summary.expect("Validation failed")
+        });
+}
 
-    process_input(black_box(reader), &black_box(validators), black_box(writer));
+#[divan::bench(args = [1, 4])]
+fn check(bencher: Bencher, threads: usize) {
+    run(bencher, false, false, threads);
+}
 
-    let duration = start.elapsed();
-    println!("Duration: {:.2?}", duration);
+#[divan::bench(args = [1, 4])]
+fn fix(bencher: Bencher, threads: usize) {
+    run(bencher, true, false, threads);
+}
 
-    #[cfg(feature = "jemalloc")]
-    {
-        let mem_after = jemalloc_ctl::stats::allocated::read().unwrap();
-        println!("Memory used: {} bytes", mem_after - mem_before);
-    }
+#[divan::bench(args = [1, 4])]
+fn check_mapped(bencher: Bencher, threads: usize) {
+    run(bencher, false, true, threads);
 }
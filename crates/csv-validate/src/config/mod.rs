@@ -1 +0,0 @@
-pub mod config;
\ No newline at end of file
@@ -1,23 +1,36 @@
 
 use std::{
-    collections::HashMap,
-    fs::File,
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
     io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::mpsc,
+    thread,
 };
 use std::io::Write;
-use std::ops::Deref;
-use aho_corasick::AhoCorasick;
-use clap::{Parser, ArgGroup, Subcommand, Args};
-use serde::Deserialize;
-use serde_yaml::Value;
-use rayon::prelude::*;
-use std::sync::{Arc, Mutex};
-pub mod config;
-
-use config::config::{load_config, CommonConfig, ValidatorSpec};
-use log::error;
-use rayon::prelude::*;
-use rayon::ThreadPoolBuilder;
+use clap::{Parser, Subcommand, Args, ValueEnum};
+
+use csv_validator_core::config::{load_config, CommonConfig, ValidatorConfig, ValidatorEntry, ValidatorSpec};
+use csv_validator_core::dataset::{Dataset, DatasetConfig};
+use csv_validator_core::engine::{process_input, process_mapped, thread_pool, Limits, Sinks, Summary, ThreadPool};
+use csv_validator_core::issue_log::{IssueLog, IssueMode, IssueStorage, DEFAULT_MAX_ISSUES_IN_MEMORY};
+use csv_validator_core::readers::compression::{compress_writer, decompress_reader, Compression};
+use csv_validator_core::readers::encoding::{
+    decode_reader, encode_writer, resolve_encoding, Encoding, InputEncoding, OutputEncoding, ENCODING_SAMPLE_SIZE,
+    UTF_8,
+};
+use csv_validator_core::readers::mmap::{map_file, Mmap};
+use csv_validator_core::utils::csv_utils::{sniff_reader, Dialect};
+use csv_validator_core::utils::paths::{expand_inputs, InputFile};
+use csv_validator_core::utils::profile::{profile_reader, Profile};
+use csv_validator_core::validators::file_validators::{
+    DuplicateRowsConfig, LineCountConfig, UniqueConfig, UniqueMode,
+};
+use csv_validator_core::validators::issue::{ErrorPolicy, Policy, Severity, ValidationIssue};
+use csv_validator_core::validators::line_validators::{FieldCountConfig, IllegalCharsConfig, Utf8Config};
+use csv_validator_core::validators::Validator;
+use std::process::ExitCode;
 
 #[derive(Debug, Clone)]
 pub struct Replacement {
@@ -26,25 +39,21 @@ pub struct Replacement {
 }
 
 
-#[derive(Debug)]
-struct ValidationResult {
-    original: String,
-    fixed: String,
-    modified: bool,
-    message: String,
-}
-
-trait Validator: Send + Sync + CloneValidator {
-    fn validate(&self, input: &str, row: usize) -> ValidationResult;
-    fn finalize(&self) {}
-    fn should_fix(&self) -> bool {
-        false
-    }
-}
-
+/// Exit codes, so the validator can be used as a gate in pipelines.
+/// Clap exits with 2 on invalid arguments.
+const EXIT_CLEAN: u8 = 0;
+const EXIT_FATAL: u8 = 1;
+const EXIT_WARNINGS: u8 = 3;
+const EXIT_ERRORS: u8 = 4;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(author, version, about, subcommand_precedence_over_arg = true)]
+#[command(after_help = "Exit codes:
+  0  no issues found
+  1  fatal error: unreadable input, invalid config, issues of severity fatal (also when aborted), ...
+  2  invalid arguments
+  3  only warnings found
+  4  errors found, validation aborted (on_error: abort), or --max-errors / --max-error-rate exceeded")]
 // #[command(group(ArgGroup::new("mode").required(true).args(&["config", "validator"])))]
 pub struct Cli {
     #[arg(long)]
@@ -54,18 +63,50 @@ pub struct Cli {
     #[command(subcommand)]
     validator: Option<ValidatorCmd>,
 
-    /// Input file (or use '-' or omit for stdin)
+    /// Input files, directories or glob patterns (or use '-' or omit for stdin)
     #[arg(value_name = "FILE")]
-    filename: Option<String>,
+    files: Vec<String>,
 
-    #[arg(long)]
+    /// Write valid and fixed rows to FILE (default: stdout); with several inputs, to files of the same name
+    /// in directory FILE (default: nowhere)
+    #[arg(long, value_name = "FILE")]
     output: Option<String>,
 
+    /// Write rows with issues that could not be fixed to FILE, or to directory FILE with several inputs;
+    /// these rows are left out of the output
+    #[arg(long, value_name = "FILE")]
+    rejects: Option<String>,
+
+    /// Append a column with the issues found to every rejected row
+    #[arg(long, requires = "rejects", default_value_t = false)]
+    reject_reason: bool,
+
     #[arg(long, value_parser = parse_char_replacement)]
     char: Vec<Replacement>,
 
-    #[arg(long, default_value_t = false)]
-    report: bool,
+    /// Write the issue report to FILE, or to stderr when no file is given
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "-", value_name = "FILE")]
+    report: Option<String>,
+
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    report_format: ReportFormat,
+
+    /// Keep at most N issues in memory; see --issue-mode for the others
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_ISSUES_IN_MEMORY)]
+    max_issues_in_memory: usize,
+
+    /// What to do with the issues beyond --max-issues-in-memory: spill (to a temporary file, all issues are
+    /// reported) or summary (only count them by validator and severity)
+    #[arg(long, default_value = "spill")]
+    issue_mode: IssueMode,
+
+    /// Stop validating once more than N errors are found
+    #[arg(long, value_name = "N")]
+    max_errors: Option<usize>,
+
+    /// Stop validating once the fraction of data rows with errors exceeds RATE (e.g. 0.01)
+    #[arg(long, value_name = "RATE")]
+    max_error_rate: Option<f64>,
 
     #[arg(long, default_value_t = num_cpus::get())]
     threads: usize,
@@ -73,17 +114,100 @@ pub struct Cli {
     #[arg(long, default_value = "100M", value_parser = parse_mem_limit)]
     mem_limit: usize,
 
+    /// Compress the output and rejects: none, gzip, zstd, bzip2 or xz (default: from their file extension,
+    /// e.g. .csv.gz). Compressed input is detected and decompressed
+    #[arg(long, value_name = "FORMAT")]
+    output_compression: Option<Compression>,
+
+    /// Read the input file as a stream, instead of mapping it in memory and reading it in parallel
+    #[arg(long, default_value_t = false)]
+    no_mmap: bool,
+
+    /// Validate the inputs as the partitions of one table: check that they have the same header, dialect and
+    /// number of fields, with the settings of the `dataset` section of the config file, if any
+    #[arg(long, default_value_t = false)]
+    dataset: bool,
+
     #[arg(long, global = true)]
     pub separator: Option<String>,
+
+    /// Encoding of the input: auto, utf-8, windows-1252, latin-1, utf-16le, utf-16be, ...
+    /// A byte order mark always wins
+    #[arg(long, default_value = "utf-8")]
+    encoding: InputEncoding,
+
+    /// Encoding of the output and rejects: input (the encoding of the input), utf-8, windows-1252, ...
+    #[arg(long, default_value = "utf-8")]
+    output_encoding: OutputEncoding,
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct PolicyArgs {
+    /// Severity of the issues found: info, warning, error or fatal
+    #[arg(long, default_value = "error")]
+    pub severity: Severity,
+
+    /// What to do with rows with issues: report, fix, drop or abort
+    #[arg(long, default_value = "drop")]
+    pub on_error: ErrorPolicy,
+}
+
+impl PolicyArgs {
+    /// The policy, with the `fix` error policy when fixing with --fix.
+    pub fn fixing(&self, fix: bool) -> Policy {
+        let on_error = if fix { ErrorPolicy::Fix } else { self.on_error };
+        Policy { on_error, ..self.into() }
+    }
+}
+
+impl From<&PolicyArgs> for Policy {
+    fn from(args: &PolicyArgs) -> Self {
+        Policy {
+            severity: args.severity,
+            on_error: args.on_error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ReportFormat {
+    /// Human readable messages
+    Text,
+    /// A single JSON array of issues
+    Json,
+    /// One JSON issue per line
+    Jsonl,
 }
 
 #[derive(Debug, Args, Clone)]
 pub struct CommonArgs {
-    /// Field separator character (default: ',')
-    #[arg(long, default_value = ",")]
+    /// Field separator (default: detected from the input)
+    #[arg(long)]
     pub separator: Option<String>,
 }
 
+#[derive(Debug, Args, Clone)]
+pub struct HeaderArgs {
+    /// The first row is a header (default: detected)
+    #[arg(long, overrides_with = "no_header")]
+    pub header: bool,
+
+    /// The first row is a record, not a header (default: detected)
+    #[arg(long, overrides_with = "header")]
+    pub no_header: bool,
+}
+
+impl HeaderArgs {
+    /// Whether the input has a header, or `None` to detect it; the last of --header and --no-header wins.
+    pub fn has_header(&self) -> Option<bool> {
+        match (self.header, self.no_header) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Subcommand, Debug)]
 pub enum ValidatorCmd {
     IllegalChars {
@@ -91,11 +215,15 @@ pub enum ValidatorCmd {
         #[arg(long, value_parser = parse_char_replacement)]
         char: Vec<Replacement>,
 
-        #[arg(long, default_value_t = false)]
+        /// Fix the rows, the same as --on-error fix
+        #[arg(long, default_value_t = false, conflicts_with = "on_error")]
         fix: bool,
 
         #[command(flatten)]
         common: CommonArgs,
+
+        #[command(flatten)]
+        policy: PolicyArgs,
     },
 
     FieldCount {
@@ -104,242 +232,161 @@ pub enum ValidatorCmd {
 
         #[command(flatten)]
         common: CommonArgs,
+
+        #[command(flatten)]
+        policy: PolicyArgs,
     },
-}
 
+    LineCount {
+        #[arg(long)]
+        min: Option<usize>,
 
-impl From<&CommonArgs> for CommonConfig {
-    fn from(args: &CommonArgs) -> Self {
-        CommonConfig {
-            separator: args.clone().separator,
-            quote_char: '"',
-            has_header: false,
+        #[arg(long)]
+        max: Option<usize>,
 
-        }
-    }
-}
+        #[command(flatten)]
+        common: CommonArgs,
 
-type ValidatorFactory = Box<dyn Fn(Value) -> Box<dyn Validator>>;
+        #[command(flatten)]
+        policy: PolicyArgs,
+    },
 
-fn build_registry() -> HashMap<String, ValidatorFactory> {
-    let mut reg: HashMap<String, ValidatorFactory> = HashMap::new();
+    /// Report bytes that aren't valid UTF-8, with their row and byte offset
+    Utf8 {
+        /// Replace invalid bytes with this character instead of U+FFFD when fixing
+        #[arg(long)]
+        replace_with: Option<char>,
 
-    reg.insert("illegal_chars".into(), Box::new(|args| {
-        let cfg: IllegalCharsConfig = serde_yaml::from_value(args).unwrap();
-        Box::new(IllegalChars::new(cfg))
-    }));
+        /// Fix the rows, the same as --on-error fix
+        #[arg(long, default_value_t = false, conflicts_with = "on_error")]
+        fix: bool,
 
-    reg.insert("field_count".into(), Box::new(|args| {
-        let cfg: FieldCountConfig = serde_yaml::from_value(args).unwrap();
-        Box::new(FieldCount::new(cfg))
-    }));
+        #[command(flatten)]
+        common: CommonArgs,
 
-    reg
-}
+        #[command(flatten)]
+        policy: PolicyArgs,
+    },
 
+    /// Report every key (the values of one or more columns) found on more than one row, with all of its rows
+    Unique {
+        /// Key column, repeat for a composite key: a 1-based position, or a name with --header
+        #[arg(long = "column", required = true)]
+        columns: Vec<String>,
 
-#[derive(Debug, Deserialize, Clone)]
-struct IllegalCharsConfig {
-    illegal_chars: Vec<String>,
-    replace_with: Vec<String>,
-    fix: bool,
-    common: CommonConfig
-}
+        #[command(flatten)]
+        header: HeaderArgs,
 
-#[derive(Clone)]
+        /// exact: keep all keys in memory; spill: keep them in memory up to --mem-limit, and on disk beyond it
+        #[arg(long, default_value = "exact")]
+        mode: UniqueMode,
 
-struct IllegalChars {
-    cfg: IllegalCharsConfig,
-    pub matcher: AhoCorasick,
-}
+        #[command(flatten)]
+        common: CommonArgs,
 
-impl IllegalChars {
-    fn new(cfg: IllegalCharsConfig) -> Self {
-        let matcher = AhoCorasick::new(&cfg.illegal_chars).expect("Failed to build matcher");
-        Self { cfg, matcher }
-    }
-}
+        #[command(flatten)]
+        policy: PolicyArgs,
+    },
 
-// first attempt, slow
-// impl Validator for IllegalChars {
-//     fn validate(&mut self, input: &str, row: usize) -> ValidationResult {
-//         let mut fixed = input.to_string();
-//         let mut modified = false;
-//         let mut message = String::new();
-//
-//
-//         for (i, c) in self.cfg.illegal_chars.iter().enumerate() {
-//             let positions: Vec<usize> = fixed.match_indices(c.as_str()).map(|(i, _)| i).collect();
-//             if positions.is_empty() {
-//
-//                 continue;
-//             } else {
-//                 modified = true;
-//                 let string_positions = positions.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ");
-//                 message.push_str(&format!("Illegal char |-> {} <-| found on line {} at positions: {}\n", c, row, string_positions));
-//                 if !self.cfg.fix {
-//                     modified = true;
-//                     continue;
-//                 }
-//                 modified = true;
-//                 let rep = self.cfg.replace_with.get(i).cloned().unwrap_or_default();
-//                 fixed = fixed.replace(c.as_str(), &rep.to_string());
-//             }
-//
-//
-//             // a lot faster if we don't care about positions and number of occurrences
-//             // if fixed.contains(c.as_str()) {
-//             //     //println!("Illegal char found!: {}", c);
-//             //     if !self.cfg.fix {
-//             //         modified = true;
-//             //         continue;
-//             //     }
-//             //     modified = true;
-//             //     let rep = self.cfg.replace_with.get(i).cloned().unwrap_or_default();
-//             //     fixed = fixed.replace(c.as_str(), &rep.to_string());
-//             // }
-//         }
-//
-//         ValidationResult {
-//             original: input.to_string(),
-//             fixed: fixed.clone(),
-//             modified,
-//             message,
-//         }
-//     }
-// }
-pub trait CloneValidator {
-    fn clone_box(&self) -> Box<dyn Validator>;
-}
+    /// Report rows that are the same as a row before them, and leave them out with --fix
+    DuplicateRows {
+        /// Compare fields without leading and trailing whitespace
+        #[arg(long, default_value_t = false)]
+        trim: bool,
 
-impl<T> CloneValidator for T
-where
-    T: Validator + Clone + 'static,
-{
-    fn clone_box(&self) -> Box<dyn Validator> {
-        Box::new(self.clone())
-    }
-}
+        /// Compare fields case-insensitively
+        #[arg(long, default_value_t = false)]
+        ignore_case: bool,
 
-impl Clone for Box<dyn Validator> {
-    fn clone(&self) -> Box<dyn Validator> {
-        self.clone_box()
-    }
-}
+        #[command(flatten)]
+        header: HeaderArgs,
 
-// second attempt using Aho-Corasick, faster for more patterns, but possibly slower for few patterns
-impl Validator for IllegalChars {
-    fn validate(&self, input: &str, row: usize) -> ValidationResult {
-        let mut fixed = input.to_string();
-        let mut modified = false;
-        let mut message = String::new();
+        /// Fix the rows, the same as --on-error fix
+        #[arg(long, default_value_t = false, conflicts_with = "on_error")]
+        fix: bool,
 
-        let mut pattern_matches: Vec<Vec<usize>> = vec![vec![]; self.cfg.illegal_chars.len()];
-        for mat in self.matcher.find_iter(input) {
-            pattern_matches[mat.pattern()].push(mat.start());
-        }
+        #[command(flatten)]
+        common: CommonArgs,
 
-        for (i, positions) in pattern_matches.into_iter().enumerate() {
-            if positions.is_empty() {
-                continue;
-            }
+        #[command(flatten)]
+        policy: PolicyArgs,
+    },
 
-            modified = true;
-            let pattern = &self.cfg.illegal_chars[i];
-            let string_positions = positions
-                .iter()
-                .map(|p| p.to_string())
-                .collect::<Vec<_>>()
-                .join(", ");
-
-            message.push_str(&format!(
-                "Illegal char found on row {} at positions: |-> {} <-|:{}\n",
-                row, pattern, string_positions
-            ));
-
-            if self.cfg.fix {
-                let rep = self.cfg.replace_with.get(i).cloned().unwrap_or_default();
-                fixed = fixed.replace(pattern, &rep);
-            }
-        }
+    /// Print the detected dialect, shape and column types of the input, without validating it
+    Sniff {
+        /// Input file (default: the global FILE, or stdin)
+        #[arg(value_name = "FILE")]
+        file: Option<String>,
 
-        ValidationResult {
-            original: input.to_string(),
-            fixed,
-            modified,
-            message,
-        }
-    }
+        /// Print a config for files like this one instead, to edit and pass to --config
+        #[arg(long, default_value_t = false)]
+        yaml: bool,
 
-    fn should_fix(&self) -> bool {
-        self.cfg.fix
-    }
+        /// Bytes read from the start of the input
+        #[arg(long, default_value = "1M", value_parser = parse_mem_limit)]
+        sample_size: usize,
+    },
 }
 
-#[derive(Debug, Deserialize, Clone)]
-struct FieldCountConfig {
-    expected: usize,
-    common: CommonConfig,
-}
 
+/// Bytes read from the input to detect its dialect.
+const SNIFF_SAMPLE_SIZE: usize = 64 * 1024;
 
-#[derive(Clone)]
-struct FieldCount {
-    cfg: FieldCountConfig,
+/// Detect the dialect of the input; the input is read from the start afterwards.
+fn sniff_input(input: &mut Input) -> io::Result<Dialect> {
+    let dialect = match input {
+        Input::Mapped(map, bom_length) => sniff_reader(&map[*bom_length..], SNIFF_SAMPLE_SIZE)?.0,
+        Input::Stream(reader) => {
+            let stream = std::mem::replace(reader, Box::new(io::empty()));
+            let (dialect, stream) = sniff_reader(stream, SNIFF_SAMPLE_SIZE)?;
+            *reader = Box::new(stream);
+            dialect
+        }
+    };
+    Ok(dialect)
 }
 
-impl FieldCount {
-    fn new(cfg: FieldCountConfig) -> Self {
-        Self { cfg }
-    }
+/// The input to validate: a local file mapped in memory, which is read in parallel, or a stream.
+enum Input {
+    /// The mapped file, and the length of the byte order mark it starts with.
+    Mapped(Mmap, usize),
+    Stream(Box<dyn BufRead>),
 }
 
-impl Validator for FieldCount {
-    fn validate(&self, input: &str, _row: usize) -> ValidationResult {
-        let sep = self.cfg.common.separator.as_deref().unwrap_or(",");
-        let actual = input.split(sep).count();
-        let mismatch = actual != self.cfg.expected;
-
-        ValidationResult {
-            original: input.to_string(),
-            fixed: input.to_string(),
-            modified: false,
-            message: if mismatch {
-                format!("Expected {}, found {}", self.cfg.expected, actual)
-            } else {
-                String::new()
-            },
+/// Open the input (stdin when there's no path), decompressed and decoded to UTF-8.
+/// Local files that aren't compressed and are in UTF-8 are mapped in memory, unless `--no-mmap` is given.
+fn open_decoded(
+    args: &Cli,
+    path: Option<&str>,
+    verbose: bool,
+) -> Result<(&'static Encoding, Input), Box<dyn std::error::Error>> {
+    let local_file = match path {
+        Some(path) if path != "-" && !args.no_mmap && std::fs::metadata(path)?.is_file() => Some(path),
+        _ => None,
+    };
+    if let Some(path) = local_file {
+        let map = map_file(path)?;
+        let (encoding, bom_length) = resolve_encoding(&map[..map.len().min(ENCODING_SAMPLE_SIZE)], args.encoding);
+        if encoding == UTF_8 && Compression::from_magic(&map) == Compression::None {
+            return Ok((encoding, Input::Mapped(map, bom_length)));
         }
     }
-    fn should_fix(&self) -> bool {
-       false
-    }
-}
-
-pub fn par_iter_enumerate_limited<T, R, F>(
-    items: &[(usize, T)],
-    max_threads: usize,
-    func: F,
-) -> Vec<R>
-where
-    T: Sync,
-    R: Send,
-    F: Fn(usize, &T) -> R + Sync + Send + 'static, // ← Add Send + 'static here
-{
-    let pool = ThreadPoolBuilder::new()
-        .num_threads(max_threads)
-        .build()
-        .expect("Failed to build rayon pool");
-
-    pool.install(|| {
-        items
-            .par_iter()
-            .map(|(i, item)| func(*i, item))
-            .collect::<Vec<R>>()
-    })
+    let (compression, reader) = decompress_reader(open_input(path)?)?;
+    if verbose && compression != Compression::None {
+        eprintln!("Detected compression: {}", compression);
+    }
+    let (encoding, reader) = decode_reader(reader, args.encoding)?;
+    Ok((encoding, Input::Stream(reader)))
 }
 
-
+impl From<&CommonArgs> for CommonConfig {
+    fn from(args: &CommonArgs) -> Self {
+        CommonConfig {
+            separator: args.clone().separator,
+            ..CommonConfig::default()
+        }
+    }
+}
 
 pub fn parse_mem_limit(s: &str) -> Result<usize, String> {
     let s = s.trim().to_lowercase();
@@ -393,111 +440,589 @@ pub fn parse_char_replacement(s: &str) -> Result<Replacement, String> {
         replace_with,
     })
 }
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> ExitCode {
     let args = Cli::parse();
-    let report = args.report;
-    let mem_limit = args.mem_limit;
-    let max_threads = args.threads;
 
-    // TODO: move this to core!
-    let registry = build_registry();
+    match run(args) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(EXIT_FATAL)
+        }
+    }
+}
 
-    let stdin_is_piped = !atty::is(atty::Stream::Stdin);
+fn run(args: Cli) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    if let Some(ValidatorCmd::Sniff { file, yaml, sample_size }) = &args.validator {
+        let file = file.as_deref().or(args.files.first().map(String::as_str));
+        let (_, reader) = decompress_reader(open_input(file)?)?;
+        let profile = profile_reader(reader, *sample_size)?;
+        if *yaml {
+            print!("{}", ValidatorConfig::skeleton(&profile).to_yaml()?);
+        } else {
+            print_profile(&profile);
+        }
+        return Ok(ExitCode::from(EXIT_CLEAN));
+    }
 
-    let reader: Box<dyn BufRead> = match &args.filename {
-        Some(path) if path != "-" => {
-            let file = File::open(path)?;
-            Box::new(BufReader::new(file))
+    let config = validator_config(&args)?;
+    let dataset = match &config.dataset {
+        Some(cfg) => Some(Dataset::new(cfg.clone())),
+        None => args.dataset.then(|| Dataset::new(DatasetConfig::default())),
+    };
+    let pool = thread_pool(args.threads)?;
+    let resources = Resources {
+        pool: &pool,
+        mem_limit: args.mem_limit,
+        issues: IssueStorage {
+            mode: args.issue_mode,
+            max_in_memory: args.max_issues_in_memory,
+        },
+    };
+
+    // a directory, a glob pattern or more than one input are validated file by file
+    let single = match args.files.as_slice() {
+        [] => true,
+        [file] => {
+            let path = Path::new(file);
+            !path.is_dir() && (path.exists() || !file.contains(['*', '?', '[']))
         }
-        Some(_) | None if stdin_is_piped => {
-            Box::new(BufReader::new(io::stdin()))
+        _ => false,
+    };
+    if !single || dataset.is_some() {
+        return validate_files(&args, &ValidatorSet::new(&config)?, dataset.as_ref(), &resources);
+    }
+
+    let destination = Destination {
+        output: match &args.output {
+            Some(path) if path != "-" => Rows::File(PathBuf::from(path)),
+            _ => Rows::Stdout,
+        },
+        rejects: args.rejects.as_ref().map(PathBuf::from),
+    };
+    let input = args.files.first().map(String::as_str);
+    let validators = ValidatorSet::new(&config)?;
+    let mut summary = validate_input(&args, &validators, input, destination, None, &resources, true)?;
+
+    if let Some(path) = &args.report {
+        write_report(&mut [(None, &mut summary.issues)], args.report_format, &mut report_writer(path)?)?;
+    }
+    if summary.issues.dropped() > 0 {
+        eprintln!(
+            "{} of {} issues were only counted (--max-issues-in-memory {})",
+            summary.issues.dropped(),
+            summary.issues.len(),
+            args.max_issues_in_memory
+        );
+    }
+
+    if let Some(reason) = &summary.aborted {
+        eprintln!("Stopped after {} rows: {}", summary.stats.rows, reason);
+    }
+
+    Ok(ExitCode::from(exit_code(&summary)))
+}
+
+/// The validators to run on every input: the ones of the config file, or the one given on the command line.
+fn validator_config(args: &Cli) -> Result<ValidatorConfig, Box<dyn std::error::Error>> {
+    let (common, spec) = match (&args.config, &args.validator) {
+        (Some(cfg_path), None) => return load_config(cfg_path),
+        (None, Some(ValidatorCmd::IllegalChars { char, fix, common, policy })) => {
+            let (illegal_chars, replace_with): (Vec<_>, Vec<_>) = char
+                .iter()
+                .map(|r| (r.pattern.clone(), r.replace_with.clone().unwrap_or_default()))
+                .unzip();
+            let spec = ValidatorSpec::IllegalChars(IllegalCharsConfig {
+                illegal_chars,
+                replace_with,
+                common: common.into(),
+                policy: policy.fixing(*fix),
+            });
+            (common.into(), spec)
         }
-        _ => {
-            eprintln!("Error: No input provided. Please provide a filename or pipe stdin.");
-            std::process::exit(1);
+
+        (None, Some(ValidatorCmd::FieldCount { expected, common, policy })) => {
+            let expected = *expected;
+            let spec = FieldCountConfig { expected, common: common.into(), policy: policy.into() };
+            (common.into(), ValidatorSpec::FieldCount(spec))
+        }
+
+        (None, Some(ValidatorCmd::LineCount { min, max, common, policy })) => {
+            let (min, max) = (*min, *max);
+            let spec = LineCountConfig { min, max, common: common.into(), policy: policy.into() };
+            (common.into(), ValidatorSpec::LineCount(spec))
+        }
+
+        (None, Some(ValidatorCmd::Utf8 { replace_with, fix, common, policy })) => {
+            let replace_with = *replace_with;
+            let spec = Utf8Config { replace_with, common: common.into(), policy: policy.fixing(*fix) };
+            (common.into(), ValidatorSpec::Utf8(spec))
+        }
+
+        (None, Some(ValidatorCmd::Unique { columns, header, mode, common, policy })) => {
+            let common = CommonConfig { has_header: header.has_header(), ..common.into() };
+            let spec = UniqueConfig {
+                columns: columns.clone(),
+                mode: *mode,
+                common: common.clone(),
+                policy: policy.into(),
+            };
+            (common, ValidatorSpec::Unique(spec))
         }
+
+        (None, Some(ValidatorCmd::DuplicateRows { trim, ignore_case, header, fix, common, policy })) => {
+            let common = CommonConfig { has_header: header.has_header(), ..common.into() };
+            let spec = DuplicateRowsConfig {
+                trim: *trim,
+                ignore_case: *ignore_case,
+                common: common.clone(),
+                policy: policy.fixing(*fix),
+            };
+            (common, ValidatorSpec::DuplicateRows(spec))
+        }
+
+        _ => unreachable!("Clap guarantees one mode; sniff is handled above"),
     };
+    Ok(ValidatorConfig {
+        common,
+        schema: None,
+        validators: vec![ValidatorEntry { enabled: true, spec }],
+        dataset: None,
+    })
+}
 
-    let mut writer: Box<dyn Write> = match &args.output {
-        Some(path) if path != "-" => {
-            let file = File::create(path)?;
-            Box::new(io::BufWriter::new(file))
+/// The validators of a run, with the dialect they were configured with.
+/// They're built once, as building them compiles their patterns and schema, and every input gets clones
+/// bound to its own dialect: the state they keep while validating (like the keys seen) is per input.
+struct ValidatorSet {
+    common: CommonConfig,
+    validators: Vec<Box<dyn Validator>>,
+}
+
+impl ValidatorSet {
+    fn new(config: &ValidatorConfig) -> Result<Self, String> {
+        Ok(Self { common: config.common.clone(), validators: config.clone().build_validators()? })
+    }
+
+    /// Fresh validators for an input with the dialect `common`.
+    fn for_input(&self, common: &CommonConfig) -> Vec<Box<dyn Validator>> {
+        let mut validators = self.validators.clone();
+        for v in &mut validators {
+            v.bind_dialect(common);
+        }
+        validators
+    }
+}
+
+/// Threads, memory and issues the validation of one input may use; the inputs share the one pool.
+struct Resources<'a> {
+    pool: &'a ThreadPool,
+    mem_limit: usize,
+    issues: IssueStorage,
+}
+
+/// Where the valid and fixed rows of an input go.
+enum Rows {
+    Stdout,
+    File(PathBuf),
+    Nowhere,
+}
+
+/// Where the rows of an input go: the valid and fixed rows, and the rejected ones.
+struct Destination {
+    output: Rows,
+    rejects: Option<PathBuf>,
+}
+
+/// Validate one input (stdin when there's no path) with clones of `validators`, and describe it as
+/// partition `index` of the dataset, if there is one.
+/// With `verbose`, what is detected about the input is printed to stderr.
+fn validate_input(
+    args: &Cli,
+    validators: &ValidatorSet,
+    path: Option<&str>,
+    destination: Destination,
+    partition: Option<(&Dataset, usize)>,
+    resources: &Resources<'_>,
+    verbose: bool,
+) -> Result<Summary, Box<dyn std::error::Error>> {
+    // validators only ever see utf-8
+    let (encoding, mut input) = open_decoded(args, path, verbose)?;
+    if verbose && args.encoding == InputEncoding::Auto {
+        eprintln!("Detected encoding: {}", encoding.name());
+    }
+    let output_encoding = args.output_encoding.resolve(encoding);
+
+    let compression_for = |path: &Path| args.output_compression.unwrap_or_else(|| Compression::from_path(path));
+    let (writer, compression): (Box<dyn Write + Send>, _) = match destination.output {
+        Rows::File(path) => (Box::new(io::BufWriter::new(File::create(&path)?)), compression_for(&path)),
+        Rows::Stdout => (Box::new(io::BufWriter::new(io::stdout())), args.output_compression.unwrap_or_default()),
+        Rows::Nowhere => (Box::new(io::sink()), Compression::None),
+    };
+    let mut writer = compress_writer(writer, compression)?;
+
+    let mut rejects_writer = match destination.rejects {
+        Some(path) => {
+            let file = io::BufWriter::new(File::create(&path)?);
+            Some(compress_writer(file, compression_for(&path))?)
         }
-        _ => Box::new(io::BufWriter::new(io::stdout())),
+        None => None,
     };
 
-    let mut validators: Vec<Box<dyn Validator>> = Vec::new();
-
-    match (&args.config, &args.validator) {
-        (Some(cfg_path), None) => {
-
-            let config = load_config(cfg_path)?;
-
-            for spec in config.validators.into_iter().filter(|v| match v {
-                ValidatorSpec::IllegalChars { enabled, common, .. } => *enabled,
-                ValidatorSpec::FieldCount { enabled, common, .. } => *enabled,
-                ValidatorSpec::LineCount { .. } => todo!(),
-            }) {
-                match spec {
-                    ValidatorSpec::IllegalChars {
-                        illegal_chars,
-                        replace_with,
-                        fix,
-                        common,
-                        ..
-                    } => {
-                        validators.push(Box::new(IllegalChars::new(IllegalCharsConfig {
-                            illegal_chars,
-                            replace_with,
-                            fix,
-                            common,
-                        })));
-                    }
-                    ValidatorSpec::FieldCount { expected, common, .. } => {
-                        validators.push(Box::new(FieldCount::new(FieldCountConfig { expected, common })));
+    // dialect of the input file itself, used to read records and the header
+    let mut input_common = validators.common.clone();
+    // the dialect of every partition is compared, even when it is configured
+    let dialect = match (input_common.needs_dialect(), partition) {
+        (false, None) => None,
+        _ => Some(sniff_input(&mut input)?),
+    };
+    if let (true, Some(dialect)) = (input_common.needs_dialect(), &dialect) {
+        if verbose {
+            eprintln!("Detected dialect: {}", dialect);
+        }
+        input_common.apply_dialect(dialect);
+    }
+    let mut validators = validators.for_input(&input_common);
+    if let Some((dataset, index)) = partition {
+        let name = path.unwrap_or("-").to_string();
+        validators.push(dataset.partition_validator(index, name, dialect, &input_common));
+    }
+
+    let mut sinks = Sinks {
+        output: encode_writer(&mut writer, output_encoding),
+        rejects: rejects_writer.as_mut().map(|rejects| encode_writer(rejects, output_encoding)),
+        reject_reason: args.reject_reason,
+        issues: resources.issues,
+    };
+    let limits = Limits {
+        max_errors: args.max_errors,
+        max_error_rate: args.max_error_rate,
+    };
+    let (mem_limit, pool) = (resources.mem_limit, resources.pool);
+    let summary = match input {
+        Input::Mapped(map, bom_length) => {
+            let data = &map[bom_length..];
+            process_mapped(data, &mut validators, &mut sinks, &input_common, &limits, mem_limit, pool)?
+        }
+        Input::Stream(reader) => {
+            process_input(reader, &mut validators, &mut sinks, &input_common, &limits, mem_limit, pool)?
+        }
+    };
+
+    // compressed streams end with a trailer, which is only written (and can only fail) when they're finished
+    drop(sinks);
+    writer.finish()?;
+    if let Some(rejects) = rejects_writer {
+        rejects.finish()?;
+    }
+    Ok(summary)
+}
+
+/// Validate the files the inputs name with the same validators, as many side by side as `--threads` allows,
+/// and print a summary per file. Output and rejects go to files of the same name in the `--output` and
+/// `--rejects` directories; the report has the issues of all files, and those of the dataset checks.
+fn validate_files(
+    args: &Cli,
+    validators: &ValidatorSet,
+    dataset: Option<&Dataset>,
+    resources: &Resources<'_>,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    if args.files.iter().any(|file| file == "-") {
+        return Err("stdin ('-') can't be validated together with other inputs".into());
+    }
+    let inputs = expand_inputs(&args.files)?;
+    let output_dir = args.output.as_deref().map(Path::new);
+    let rejects_dir = args.rejects.as_deref().map(Path::new);
+
+    let mut names = HashMap::new();
+    for input in &inputs {
+        for dir in output_dir.iter().chain(&rejects_dir) {
+            if let Some(other) = names.insert(dir.join(&input.name), &input.path) {
+                let target = dir.join(&input.name);
+                return Err(format!(
+                    "{} and {} would both be written to {}",
+                    other.display(),
+                    input.path.display(),
+                    target.display()
+                )
+                .into());
+            }
+            if let Some(parent) = dir.join(&input.name).parent() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+    }
+
+    // the files validated at the same time validate on the same pool, and each gets its share of the memory;
+    // the issues of all files are kept until the report is written
+    let parallel = resources.pool.current_num_threads().min(inputs.len()).max(1);
+    let share = Resources {
+        pool: resources.pool,
+        mem_limit: resources.mem_limit / parallel,
+        issues: IssueStorage {
+            max_in_memory: (resources.issues.max_in_memory / inputs.len().max(1)).max(1),
+            ..resources.issues
+        },
+    };
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..parallel {
+            let (sender, next, inputs, share) = (sender.clone(), &next, &inputs, &share);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(input) = inputs.get(index) else {
+                        break;
+                    };
+                    let destination = Destination {
+                        output: match output_dir {
+                            Some(dir) => Rows::File(dir.join(&input.name)),
+                            None => Rows::Nowhere,
+                        },
+                        rejects: rejects_dir.map(|dir| dir.join(&input.name)),
+                    };
+                    let path = input.path.to_string_lossy();
+                    let partition = dataset.map(|dataset| (dataset, index));
+                    let summary = validate_input(args, validators, Some(&path), destination, partition, share, false)
+                        .map_err(|e| e.to_string());
+                    if sender.send((input, summary)).is_err() {
+                        break;
                     }
-                   ValidatorSpec::LineCount { .. } => todo!(),
                 }
-            }
+            });
         }
-        (None, Some(ValidatorCmd::IllegalChars { char, fix, common, .. })) => {
+    });
+    drop(sender);
 
-            let (illegal_chars, replace_with): (Vec<_>, Vec<_>) = char
-                .into_iter()
-                .map(|r| (r.clone().pattern, r.clone().replace_with.unwrap_or_default()))
-                .unzip();            let fix = *fix;
-            let common = common.into();
-            validators.push(Box::new(IllegalChars::new(IllegalCharsConfig {
-                illegal_chars,
-                replace_with,
-                fix,
-                common,
-            })));
+    let mut results: Vec<(&InputFile, Result<Summary, String>)> = receiver.into_iter().collect();
+    results.sort_by_key(|(input, _)| inputs.iter().position(|other| other == *input));
+    print_file_summaries(&results);
+
+    // the issues of the dataset checks name their files themselves
+    let mut dataset_issues = IssueLog::new(resources.issues);
+    if let Some(dataset) = dataset {
+        dataset_issues.extend(dataset.finish())?;
+        eprintln!("dataset: {} issues across the files", dataset_issues.len());
+    }
+    let dataset_code = severity_exit_code(dataset_issues.max_severity());
+
+    let mut logs: Vec<(Option<String>, &mut IssueLog)> = results
+        .iter_mut()
+        .filter_map(|(input, summary)| {
+            let summary = summary.as_mut().ok()?;
+            Some((Some(input.path.display().to_string()), &mut summary.issues))
+        })
+        .collect();
+    logs.push((None, &mut dataset_issues));
+    if let Some(path) = &args.report {
+        write_report(&mut logs, args.report_format, &mut report_writer(path)?)?;
+    }
+    let dropped: usize = logs.iter().map(|(_, log)| log.dropped()).sum();
+    if dropped > 0 {
+        let total: usize = logs.iter().map(|(_, log)| log.len()).sum();
+        eprintln!(
+            "{} of {} issues were only counted (--max-issues-in-memory {}, for all files together)",
+            dropped, total, args.max_issues_in_memory
+        );
+    }
+
+    let codes = results.iter().map(|(_, summary)| match summary {
+        Ok(summary) => exit_code(summary),
+        Err(_) => EXIT_FATAL,
+    });
+    Ok(ExitCode::from(worst_exit_code(codes.chain([dataset_code]))))
+}
+
+/// Print the rows, errors and warnings of every file, and the totals, to stderr.
+fn print_file_summaries(results: &[(&InputFile, Result<Summary, String>)]) {
+    let width = results.iter().map(|(input, _)| input.path.display().to_string().len()).max().unwrap_or(0);
+    let (mut rows, mut errors, mut warnings, mut failed) = (0, 0, 0, 0);
+
+    eprintln!("\n{:<width$}  {:>10}  {:>8}  {:>8}", "file", "rows", "errors", "warnings", width = width);
+    for (input, summary) in results {
+        let path = input.path.display();
+        match summary {
+            Ok(summary) => {
+                let file_warnings: usize = summary
+                    .issues
+                    .counts()
+                    .filter(|(_, severity, _)| *severity == Severity::Warning)
+                    .map(|(_, _, count)| count)
+                    .sum();
+                let stats = &summary.stats;
+                let stopped = summary.aborted.as_ref().map(|reason| format!("  stopped: {}", reason));
+                eprintln!(
+                    "{:<width$}  {:>10}  {:>8}  {:>8}{}",
+                    path,
+                    stats.rows,
+                    stats.errors,
+                    file_warnings,
+                    stopped.unwrap_or_default(),
+                    width = width
+                );
+                rows += stats.rows;
+                errors += stats.errors;
+                warnings += file_warnings;
+            }
+            Err(e) => {
+                eprintln!("{:<width$}  failed: {}", path, e, width = width);
+                failed += 1;
+            }
         }
+    }
+    eprintln!(
+        "{} files: {} rows, {} errors, {} warnings, {} failed",
+        results.len(),
+        rows,
+        errors,
+        warnings,
+        failed
+    );
+}
 
-        (None, Some(ValidatorCmd::FieldCount { expected, common, .. })) => {
-            let expected = *expected;
-            let common = common.into();
-            validators.push(Box::new(FieldCount::new(FieldCountConfig { expected, common })));
+fn report_writer(path: &str) -> io::Result<Box<dyn Write>> {
+    Ok(match path {
+        "-" => Box::new(io::stderr()),
+        path => Box::new(io::BufWriter::new(File::create(path)?)),
+    })
+}
+
+fn open_input(filename: Option<&str>) -> Result<Box<dyn BufRead>, Box<dyn std::error::Error>> {
+    let stdin_is_piped = !atty::is(atty::Stream::Stdin);
+
+    match filename {
+        Some(path) if path != "-" => {
+            let file = File::open(path)?;
+            Ok(Box::new(BufReader::new(file)))
+        }
+        Some(_) | None if stdin_is_piped => {
+            Ok(Box::new(BufReader::new(io::stdin())))
+        }
+        _ => {
+            Err("No input provided. Please provide a filename or pipe stdin.".into())
         }
+    }
+}
+
+fn print_profile(profile: &Profile) {
+    let dialect = &profile.dialect;
+    let quote = dialect.quote_char.map_or("none".to_string(), |q| format!("{:?}", q));
+    println!("encoding:       {}", profile.encoding);
+    println!("separator:      {:?}", dialect.separator);
+    println!("quote char:     {}", quote);
+    println!("escape:         {:?}", dialect.escape);
+    println!("header:         {}", if profile.header.is_some() { "yes" } else { "no" });
+    println!("line endings:   {}", dialect.line_terminator);
+    println!("confidence:     {:.2}", dialect.confidence);
+    println!("records:        {}", profile.records);
+
+    println!("\nfield counts:");
+    for (fields, count) in &profile.field_counts {
+        println!("  {:>6} fields: {} records", fields, count);
+    }
 
-        _ => unreachable!("Clap guarantees one mode"),
+    println!("\ncolumns:");
+    for (i, column) in profile.columns.iter().enumerate() {
+        let nullable = if column.nullable { ", nullable" } else { "" };
+        println!("  {:>3}  {}: {}{}", i + 1, column.name, column.column_type, nullable);
     }
+}
 
-    process_input(reader, &mut validators, &mut writer, mem_limit, report, max_threads)?;
+fn exit_code(summary: &Summary) -> u8 {
+    let code = severity_exit_code(summary.max_severity());
+    // stopping early is at least an error, and a fatal issue it stopped at stays fatal
+    if summary.aborted.is_some() {
+        return worst_exit_code([code, EXIT_ERRORS]);
+    }
+    code
+}
 
-    Ok(())
+fn severity_exit_code(severity: Option<Severity>) -> u8 {
+    match severity {
+        Some(Severity::Fatal) => EXIT_FATAL,
+        Some(Severity::Error) => EXIT_ERRORS,
+        Some(Severity::Warning) => EXIT_WARNINGS,
+        Some(Severity::Info) | None => EXIT_CLEAN,
+    }
 }
 
-fn print_report(messages: &[String]) {
-    if messages.is_empty() {
-        return;
+/// The exit code of the worst outcome: fatal, then errors, then warnings.
+fn worst_exit_code(codes: impl IntoIterator<Item = u8>) -> u8 {
+    const ORDER: [u8; 4] = [EXIT_CLEAN, EXIT_WARNINGS, EXIT_ERRORS, EXIT_FATAL];
+    codes
+        .into_iter()
+        .max_by_key(|code| ORDER.iter().position(|c| c == code))
+        .unwrap_or(EXIT_CLEAN)
+}
+
+/// Call `f` with the issues of the logs, in order; the issues are tagged with the file of their log, if it has one.
+fn for_each_issue(
+    logs: &mut [(Option<String>, &mut IssueLog)],
+    mut f: impl FnMut(ValidationIssue) -> io::Result<()>,
+) -> io::Result<()> {
+    for (file, log) in logs {
+        for issue in log.issues()? {
+            let mut issue = issue?;
+            if file.is_some() {
+                issue.file = file.clone();
+            }
+            f(issue)?;
+        }
     }
+    Ok(())
+}
 
-    eprintln!("\nErrors:");
-    for msg in messages {
-        eprintln!("  {}", msg);
+/// Write the issues of the logs, of one file or of several files.
+fn write_report<W: Write>(
+    logs: &mut [(Option<String>, &mut IssueLog)],
+    format: ReportFormat,
+    writer: &mut W,
+) -> io::Result<()> {
+    match format {
+        ReportFormat::Text => {
+            if logs.iter().any(|(_, log)| !log.is_empty()) {
+                writeln!(writer, "\nErrors:")?;
+                for_each_issue(logs, |issue| match &issue.file {
+                    Some(file) => writeln!(writer, "  {}: {}", file, issue.message),
+                    None => writeln!(writer, "  {}", issue.message),
+                })?;
+            }
+            let dropped: usize = logs.iter().map(|(_, log)| log.dropped()).sum();
+            if dropped > 0 {
+                writeln!(writer, "  ... and {} more", dropped)?;
+                writeln!(writer, "\nIssues by validator:")?;
+                let mut counts = BTreeMap::new();
+                for (_, log) in logs.iter() {
+                    for (validator, severity, count) in log.counts() {
+                        *counts.entry((validator, severity)).or_insert(0) += count;
+                    }
+                }
+                for ((validator, severity), count) in counts {
+                    writeln!(writer, "  {:<16} {:<8} {}", validator, severity, count)?;
+                }
+            }
+        }
+        ReportFormat::Json => {
+            // streamed, as the issues may not fit in memory; laid out like a pretty printed array
+            let mut first = true;
+            for_each_issue(logs, |issue| {
+                let json = serde_json::to_string_pretty(&issue)?.replace('\n', "\n  ");
+                write!(writer, "{}  {}", if first { "[\n" } else { ",\n" }, json)?;
+                first = false;
+                Ok(())
+            })?;
+            writeln!(writer, "{}", if first { "[]" } else { "\n]" })?;
+        }
+        ReportFormat::Jsonl => {
+            for_each_issue(logs, |issue| {
+                serde_json::to_writer(&mut *writer, &issue)?;
+                writeln!(writer)
+            })?;
+        }
     }
+    writer.flush()
 }
 
 // replaced this with a parallel version
@@ -545,108 +1070,3 @@ fn print_report(messages: &[String]) {
 //     Ok(())
 // }
 //
-pub fn process_input<R: BufRead, W: Write>(
-    reader: R,
-    validators: &[Box<dyn Validator>],
-    writer: &mut W,
-    mem_limit_bytes: usize,
-    report: bool,
-    max_threads: usize,
-) -> std::io::Result<()> {
-    let fix_enabled = validators.iter().any(|v| v.should_fix());
-    let writer: Arc<Mutex<&mut dyn Write>> = Arc::new(Mutex::new(writer as &mut dyn Write));
-    let error_messages = Arc::new(Mutex::new(Vec::new()));
-    let validators_arc = Arc::new(validators); // ✅ no cloning
-
-    let mut batch = Vec::new();
-    let mut total_bytes = 0;
-
-    for (row, line) in reader.lines().enumerate() {
-        let line = line?;
-        total_bytes += line.len();
-        batch.push((row, line));
-
-        if total_bytes >= mem_limit_bytes {
-            process_batch(&batch, Arc::clone(&validators_arc), fix_enabled, &writer, Arc::clone(&error_messages), max_threads)?;
-            batch.clear();
-            total_bytes = 0;
-        }
-    }
-
-    if !batch.is_empty() {
-        process_batch(&batch, Arc::clone(&validators_arc), fix_enabled, &writer, Arc::clone(&error_messages), max_threads)?;
-    }
-
-    writer.lock().unwrap().flush()?;
-    if report {
-        print_report(&error_messages.lock().unwrap());
-    }
-
-    Ok(())
-}
-
-fn process_batch(
-    batch: &[(usize, String)],
-    validators: Arc<&[Box<dyn Validator>]>,
-    fix_enabled: bool,
-    writer: &Arc<Mutex<&mut dyn std::io::Write>>,
-    error_messages: Arc<Mutex<Vec<String>>>,
-    max_threads: usize,
-) -> std::io::Result<()> {
-    let results: Vec<String> = par_iter_enumerate_limited(batch, max_threads, {
-        let validators: Arc<Vec<Box<dyn Validator>>> = Arc::new(validators.to_vec());
-
-        // let validators = Arc::clone(&validators);
-        let error_messages = Arc::clone(&error_messages);
-
-        move |row, line| {
-            let mut result = ValidationResult {
-                original: line.clone(),
-                fixed: line.clone(),
-                modified: false,
-                message: String::new(),
-            };
-
-            if fix_enabled {
-                for v in validators.iter() {
-                    let updated = v.validate(&result.fixed, row + 1);
-                    result.fixed = updated.fixed;
-                    result.modified |= updated.modified;
-                    if !updated.message.is_empty() {
-                        result.message.push_str(&updated.message);
-                        result.message.push(' ');
-                        error_messages.lock().unwrap().push(updated.message);
-                    }
-                }
-            } else {
-                let updates: Vec<_> = validators
-                    .par_iter()
-                    .map(|v| v.validate(&result.fixed, row + 1))
-                    .collect();
-                for updated in updates {
-                    result.modified |= updated.modified;
-                    if !updated.message.is_empty() {
-                        result.message.push_str(&updated.message);
-                        result.message.push(' ');
-                        error_messages.lock().unwrap().push(updated.message);
-                    }
-                }
-            }
-
-            if result.modified {
-                result.fixed
-            } else if !result.message.trim().is_empty() {
-                format!("-> {}", result.message.trim())
-            } else {
-                result.original
-            }
-        }
-    });
-
-    let mut w = writer.lock().unwrap();
-    for line in results {
-        writeln!(w, "{}", line)?;
-    }
-
-    Ok(())
-}
\ No newline at end of file
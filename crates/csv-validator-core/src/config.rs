@@ -0,0 +1,302 @@
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::DatasetConfig;
+use crate::utils::csv_utils::{Dialect, LineTerminator};
+use crate::utils::profile::Profile;
+use crate::validators::Validator;
+use crate::validators::column_validators::{
+    ColumnTypes, ColumnTypesConfig, Header, HeaderConfig, HeaderMode, Schema,
+};
+use crate::validators::file_validators::{
+    DuplicateRows, DuplicateRowsConfig, LineCount, LineCountConfig, Unique, UniqueConfig,
+};
+use crate::validators::issue::Policy;
+use crate::validators::line_validators::{
+    FieldCount, FieldCountConfig, IllegalChars, IllegalCharsConfig, Separator, SeparatorConfig, Utf8,
+    Utf8Config,
+};
+
+/// A validation run, as read from a yaml config file.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::config::ValidatorConfig;
+///
+/// let config: ValidatorConfig = serde_yaml::from_str(r#"
+/// common:
+///   quote_char: '"'
+///   separator: ';'
+///   has_header: true
+/// validators:
+///   - type: field_count
+///     expected: 5
+///     enabled: true
+///     common:
+///       quote_char: '"'
+///       separator: ';'
+///       has_header: true
+///   - type: line_count
+///     min: 1
+///     enabled: false
+///     common:
+///       quote_char: '"'
+///       separator: ';'
+///       has_header: true
+/// "#).unwrap();
+///
+/// let validators = config.build_validators().unwrap();
+/// assert_eq!(validators.len(), 1);
+/// assert_eq!(validators[0].name(), "field_count");
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ValidatorConfig {
+    pub common: CommonConfig,
+    /// Column names and types, used by the `column_types` validator.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Schema>,
+    pub validators: Vec<ValidatorEntry>,
+    /// Checks across the files of a dataset, when the files are validated as one table.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dataset: Option<DatasetConfig>,
+}
+
+/// The dialect of the input. What isn't set is detected from the input, or else defaults to a `"` quote,
+/// no header and a `,` separator.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CommonConfig {
+    pub quote_char: Option<char>,
+    pub separator: Option<String>,
+    pub has_header: Option<bool>,
+    /// `lf`, `crlf` or `cr`: lines that end at CR alone are only read as such when it's set (or detected).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_terminator: Option<LineTerminator>,
+}
+
+impl CommonConfig {
+    pub fn quote_char(&self) -> char {
+        self.quote_char.unwrap_or('"')
+    }
+
+    pub fn has_header(&self) -> bool {
+        self.has_header.unwrap_or(false)
+    }
+
+    pub fn line_terminator(&self) -> LineTerminator {
+        self.line_terminator.unwrap_or_default()
+    }
+
+    /// Whether part of the dialect is left to be detected.
+    pub fn needs_dialect(&self) -> bool {
+        self.separator.is_none()
+            || self.quote_char.is_none()
+            || self.has_header.is_none()
+            || self.line_terminator.is_none()
+    }
+
+    /// Use the detected dialect for what isn't configured.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use csv_validator_core::config::CommonConfig;
+    /// use csv_validator_core::utils::csv_utils::sniff_dialect;
+    ///
+    /// let mut common = CommonConfig { separator: Some(";".to_string()), ..CommonConfig::default() };
+    /// common.apply_dialect(&sniff_dialect("'id'|'name'\n1|'a'\n2|'b'\n"));
+    ///
+    /// assert_eq!(common.separator.as_deref(), Some(";"));
+    /// assert_eq!(common.quote_char(), '\'');
+    /// assert!(common.has_header());
+    /// ```
+    pub fn apply_dialect(&mut self, dialect: &Dialect) {
+        if self.separator.is_none() {
+            self.separator = Some(dialect.separator.clone());
+        }
+        // without quotes in the sample, the default quote char does no harm
+        if self.quote_char.is_none() {
+            self.quote_char = dialect.quote_char;
+        }
+        if self.has_header.is_none() {
+            self.has_header = Some(dialect.has_header);
+        }
+        if self.line_terminator.is_none() {
+            self.line_terminator = Some(dialect.line_terminator);
+        }
+    }
+
+    /// Use the dialect of `other` for what isn't configured.
+    pub fn inherit(&mut self, other: &CommonConfig) {
+        if self.separator.is_none() {
+            self.separator = other.separator.clone();
+        }
+        if self.quote_char.is_none() {
+            self.quote_char = other.quote_char;
+        }
+        if self.has_header.is_none() {
+            self.has_header = other.has_header;
+        }
+        if self.line_terminator.is_none() {
+            self.line_terminator = other.line_terminator;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ValidatorEntry {
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub spec: ValidatorSpec,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ValidatorSpec {
+    IllegalChars(IllegalCharsConfig),
+    LineCount(LineCountConfig),
+    FieldCount(FieldCountConfig),
+    Separator(SeparatorConfig),
+    ColumnTypes(ColumnTypesConfig),
+    Header(HeaderConfig),
+    Utf8(Utf8Config),
+    Unique(UniqueConfig),
+    DuplicateRows(DuplicateRowsConfig),
+}
+
+impl ValidatorSpec {
+    pub fn common_mut(&mut self) -> &mut CommonConfig {
+        match self {
+            ValidatorSpec::IllegalChars(cfg) => &mut cfg.common,
+            ValidatorSpec::LineCount(cfg) => &mut cfg.common,
+            ValidatorSpec::FieldCount(cfg) => &mut cfg.common,
+            ValidatorSpec::Separator(cfg) => &mut cfg.common,
+            ValidatorSpec::ColumnTypes(cfg) => &mut cfg.common,
+            ValidatorSpec::Header(cfg) => &mut cfg.common,
+            ValidatorSpec::Utf8(cfg) => &mut cfg.common,
+            ValidatorSpec::Unique(cfg) => &mut cfg.common,
+            ValidatorSpec::DuplicateRows(cfg) => &mut cfg.common,
+        }
+    }
+
+    /// Build the validator; `schema` is the top-level schema, used by `column_types` when it has none of its own.
+    pub fn build(self, schema: Option<&Schema>) -> Result<Box<dyn Validator>, String> {
+        Ok(match self {
+            ValidatorSpec::IllegalChars(cfg) => Box::new(IllegalChars::new(cfg)),
+            ValidatorSpec::LineCount(cfg) => Box::new(LineCount::new(cfg)),
+            ValidatorSpec::FieldCount(cfg) => Box::new(FieldCount::new(cfg)),
+            ValidatorSpec::Separator(cfg) => Box::new(Separator::new(cfg)),
+            ValidatorSpec::ColumnTypes(mut cfg) => {
+                if cfg.schema.is_none() {
+                    cfg.schema = Some(
+                        schema
+                            .cloned()
+                            .ok_or("the column_types validator requires a schema section")?,
+                    );
+                }
+                Box::new(ColumnTypes::new(cfg))
+            }
+            ValidatorSpec::Header(cfg) => Box::new(Header::new(cfg)),
+            ValidatorSpec::Utf8(cfg) => Box::new(Utf8::new(cfg)),
+            ValidatorSpec::Unique(cfg) => Box::new(Unique::new(cfg)),
+            ValidatorSpec::DuplicateRows(cfg) => Box::new(DuplicateRows::new(cfg)),
+        })
+    }
+}
+
+impl ValidatorConfig {
+    /// Build the enabled validators, in config order.
+    /// Validators use the top-level separator, quote char and header setting, unless they have their own.
+    pub fn build_validators(self) -> Result<Vec<Box<dyn Validator>>, String> {
+        let schema = self.schema;
+        let common = self.common;
+        self.validators
+            .into_iter()
+            .filter(|entry| entry.enabled)
+            .map(|mut entry| {
+                entry.spec.common_mut().inherit(&common);
+                entry.spec.build(schema.as_ref())
+            })
+            .collect()
+    }
+
+    /// A config to start from for files like the profiled one: its dialect, the inferred schema,
+    /// and the header, field count, column type and line count checks, all with the default policy.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use csv_validator_core::config::ValidatorConfig;
+    /// use csv_validator_core::utils::profile::profile_sample;
+    ///
+    /// let profile = profile_sample(b"id|name\n1|a\n2|b\n", false);
+    /// let config = ValidatorConfig::skeleton(&profile);
+    ///
+    /// assert_eq!(config.common.separator.as_deref(), Some("|"));
+    /// assert!(config.common.has_header());
+    /// assert_eq!(config.validators.len(), 4);
+    ///
+    /// // the yaml reads back as the same config
+    /// let yaml = config.to_yaml().unwrap();
+    /// let config: ValidatorConfig = serde_yaml::from_str(&yaml).unwrap();
+    /// assert_eq!(config.build_validators().unwrap().len(), 4);
+    /// ```
+    pub fn skeleton(profile: &Profile) -> ValidatorConfig {
+        let dialect = &profile.dialect;
+        let common = CommonConfig {
+            quote_char: Some(dialect.quote_char.unwrap_or('"')),
+            separator: Some(dialect.separator.clone()),
+            has_header: Some(profile.header.is_some()),
+            line_terminator: Some(dialect.line_terminator),
+        };
+        let expected = profile.field_counts.first().map_or(0, |&(fields, _)| fields);
+
+        let mut specs = Vec::new();
+        if let Some(header) = &profile.header {
+            specs.push(ValidatorSpec::Header(HeaderConfig {
+                expected: header.clone(),
+                mode: HeaderMode::Exact,
+                common: common.clone(),
+                policy: Policy::default(),
+            }));
+        }
+        specs.push(ValidatorSpec::FieldCount(FieldCountConfig {
+            expected,
+            common: common.clone(),
+            policy: Policy::default(),
+        }));
+        specs.push(ValidatorSpec::ColumnTypes(ColumnTypesConfig {
+            schema: None,
+            common: common.clone(),
+            policy: Policy::default(),
+        }));
+        specs.push(ValidatorSpec::LineCount(LineCountConfig {
+            min: Some(1),
+            max: None,
+            common: common.clone(),
+            policy: Policy::default(),
+        }));
+
+        ValidatorConfig {
+            common,
+            schema: Some(Schema {
+                columns: profile.columns.clone(),
+            }),
+            validators: specs
+                .into_iter()
+                .map(|spec| ValidatorEntry { enabled: true, spec })
+                .collect(),
+            dataset: None,
+        }
+    }
+
+    pub fn to_yaml(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+}
+
+pub fn load_config(filename: &str) -> Result<ValidatorConfig, Box<dyn std::error::Error>> {
+    let file = std::fs::File::open(filename)?;
+    let reader = std::io::BufReader::new(file);
+    let config = serde_yaml::from_reader(reader)?;
+    Ok(config)
+}
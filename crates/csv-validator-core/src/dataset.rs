@@ -0,0 +1,499 @@
+//! Dataset mode: the files of a table split into partitions are checked against each other, on top of
+//! the checks of every file on its own. The partitions are described while they are validated, by a
+//! validator added to the validators of every file, and compared once all of them are done.
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::CommonConfig;
+use crate::utils::csv_utils::Dialect;
+use crate::utils::tokenizer::Fields;
+use crate::validators::file_validators::{KeyColumns, KeyRow, KeyStore, display_key};
+use crate::validators::issue::{Policy, Severity, ValidationIssue, ValidationResult};
+use crate::validators::{Accumulator, Validator, downcast_accumulator};
+
+/// The checks across the partitions of a dataset, from the `dataset` section of the config file.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::dataset::DatasetConfig;
+///
+/// let config: DatasetConfig = serde_yaml::from_str("unique: [id]\nmax_rows: 1000000\n").unwrap();
+///
+/// assert!(config.same_header && config.same_dialect && config.same_field_count);
+/// assert_eq!(config.unique, ["id"]);
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DatasetConfig {
+    /// All partitions have the header of the first one.
+    #[serde(default = "default_true")]
+    pub same_header: bool,
+    /// All partitions have the separator, quote char, escape style and line terminator of the first one.
+    #[serde(default = "default_true")]
+    pub same_dialect: bool,
+    /// All partitions have as many fields as the first one, going by their first record.
+    #[serde(default = "default_true")]
+    pub same_field_count: bool,
+    /// Key columns, by name, or by position (1-based) without a header: a key may not be in more than
+    /// one partition.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unique: Vec<String>,
+    /// Bounds of the number of data records of all partitions together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_rows: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rows: Option<usize>,
+    #[serde(default)]
+    pub severity: Severity,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for DatasetConfig {
+    fn default() -> Self {
+        Self {
+            same_header: true,
+            same_dialect: true,
+            same_field_count: true,
+            unique: Vec::new(),
+            min_rows: None,
+            max_rows: None,
+            severity: Severity::default(),
+        }
+    }
+}
+
+/// What dataset mode knows about a partition once it is validated.
+#[derive(Debug)]
+struct Partition {
+    name: String,
+    dialect: Option<Dialect>,
+    header: Option<Vec<String>>,
+    /// Number of fields of the first record.
+    field_count: Option<usize>,
+    /// Number of data records, the header excluded.
+    rows: usize,
+}
+
+/// The partitions of a dataset, described while they are validated, possibly at the same time.
+///
+/// # Example
+///
+/// ```
+/// use std::io;
+/// use csv_validator_core::config::CommonConfig;
+/// use csv_validator_core::dataset::{Dataset, DatasetConfig};
+/// use csv_validator_core::engine::{process_input, thread_pool, Limits, Sinks};
+/// use csv_validator_core::issue_log::IssueStorage;
+///
+/// let common = CommonConfig { separator: Some(";".to_string()), has_header: Some(true), ..CommonConfig::default() };
+/// let dataset = Dataset::new(DatasetConfig { unique: vec!["id".to_string()], ..DatasetConfig::default() });
+/// let pool = thread_pool(1).unwrap();
+///
+/// for (index, data) in ["id;name\n1;a\n2;b\n", "id;name\n3;c\n1;d\n"].iter().enumerate() {
+///     let mut validators = vec![dataset.partition_validator(index, format!("part-{}.csv", index), None, &common)];
+///     let mut sinks = Sinks {
+///         output: Box::new(io::sink()),
+///         rejects: None,
+///         reject_reason: false,
+///         issues: IssueStorage::default(),
+///     };
+///     process_input(data.as_bytes(), &mut validators, &mut sinks, &common, &Limits::default(), 1024, &pool).unwrap();
+/// }
+///
+/// let issues = dataset.finish();
+/// assert_eq!(issues[0].file.as_deref(), Some("part-1.csv"));
+/// assert_eq!(issues[0].message, "Key 1 on row 3 is also in part-0.csv, on row 2");
+/// ```
+pub struct Dataset {
+    cfg: DatasetConfig,
+    partitions: Arc<Mutex<BTreeMap<usize, Partition>>>,
+    /// The keys of all partitions, tagged with their partition, spilled to disk beyond the memory limit.
+    keys: Arc<Mutex<Option<KeyStore>>>,
+}
+
+impl Dataset {
+    pub fn new(cfg: DatasetConfig) -> Self {
+        Self {
+            cfg,
+            partitions: Arc::new(Mutex::new(BTreeMap::new())),
+            keys: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// The validator that describes partition `index` (in the order of the dataset) while it is validated,
+    /// to add to the validators of the partition. `dialect` is the one detected, if it was.
+    /// Partitions that aren't validated to the end are left out of the checks.
+    pub fn partition_validator(
+        &self,
+        index: usize,
+        name: String,
+        dialect: Option<Dialect>,
+        common: &CommonConfig,
+    ) -> Box<dyn Validator> {
+        Box::new(PartitionCollector {
+            index,
+            name,
+            dialect,
+            common: common.clone(),
+            key_columns: KeyColumns::new(&self.cfg.unique),
+            severity: self.cfg.severity,
+            header: None,
+            mem_limit: usize::MAX,
+            partitions: Arc::clone(&self.partitions),
+            keys: Arc::clone(&self.keys),
+        })
+    }
+
+    /// Compare the partitions with the first one, check that keys are in one partition only,
+    /// and count the rows of all partitions. Issues are about the later partition of the two compared.
+    pub fn finish(&self) -> Vec<ValidationIssue> {
+        let by_index = self.partitions.lock().unwrap_or_else(|e| e.into_inner());
+        let partitions: Vec<&Partition> = by_index.values().collect();
+        let mut issues = Vec::new();
+        let mut issue = |file: Option<&str>, row: usize, message: String| {
+            issues.push(ValidationIssue {
+                file: file.map(str::to_string),
+                severity: self.cfg.severity,
+                ..ValidationIssue::new("dataset", row, message)
+            });
+        };
+
+        if let Some((first, rest)) = partitions.split_first() {
+            for partition in rest {
+                let name = Some(partition.name.as_str());
+                if self.cfg.same_header && partition.header != first.header {
+                    let message = format!(
+                        "Header {:?} differs from the header of {}: {:?}",
+                        partition.header.as_deref().unwrap_or_default(),
+                        first.name,
+                        first.header.as_deref().unwrap_or_default()
+                    );
+                    issue(name, 0, message);
+                }
+                if self.cfg.same_dialect
+                    && let (Some(dialect), Some(expected)) = (&partition.dialect, &first.dialect)
+                    && let Some(difference) = dialect_difference(dialect, expected)
+                {
+                    issue(name, 0, format!("Dialect differs from the one of {}: {}", first.name, difference));
+                }
+                if self.cfg.same_field_count
+                    && let (Some(fields), Some(expected)) = (partition.field_count, first.field_count)
+                    && fields != expected
+                {
+                    issue(name, 0, format!("{} fields, where {} has {}", fields, first.name, expected));
+                }
+            }
+        }
+
+        let keys = self.keys.lock().unwrap_or_else(|e| e.into_inner()).take();
+        match keys.map(KeyStore::duplicates).transpose() {
+            Ok(duplicates) => {
+                let name = |row: &KeyRow| by_index.get(&row.partition).map_or("", |partition| &partition.name);
+                // the first row of the key in every partition it is in, in the order of the partitions
+                let mut repeated: Vec<(KeyRow, String)> = Vec::new();
+                for (key, mut rows) in duplicates.unwrap_or_default() {
+                    rows.sort_unstable();
+                    rows.dedup_by_key(|row| row.partition);
+                    let Some((first, rest)) = rows.split_first() else { continue };
+                    for row in rest {
+                        let message = format!(
+                            "Key {} on row {} is also in {}, on row {}",
+                            display_key(&key),
+                            row.row,
+                            name(first),
+                            first.row
+                        );
+                        repeated.push((*row, message));
+                    }
+                }
+                repeated.sort_unstable_by_key(|(row, _)| *row);
+                for (row, message) in repeated {
+                    issue(Some(name(&row)), row.row, message);
+                }
+            }
+            Err(error) => issue(None, 0, format!("Keys could not be checked across partitions: {}", error)),
+        }
+
+        // the row count isn't about a single partition
+        let rows: usize = partitions.iter().map(|partition| partition.rows).sum();
+        match (self.cfg.min_rows, self.cfg.max_rows) {
+            (Some(min), _) if rows < min => {
+                issue(None, 0, format!("Expected at least {} records in the dataset, found {}", min, rows))
+            }
+            (_, Some(max)) if rows > max => {
+                issue(None, 0, format!("Expected at most {} records in the dataset, found {}", max, rows))
+            }
+            _ => {}
+        }
+        issues
+    }
+}
+
+/// How `dialect` differs from `expected`, if it does.
+fn dialect_difference(dialect: &Dialect, expected: &Dialect) -> Option<String> {
+    let mut differences = Vec::new();
+    if dialect.separator != expected.separator {
+        differences.push(format!("separator {:?} instead of {:?}", dialect.separator, expected.separator));
+    }
+    // quotes are only detected in files that have quoted fields
+    if let (Some(quote), Some(expected_quote)) = (dialect.quote_char, expected.quote_char) {
+        if quote != expected_quote {
+            differences.push(format!("quote {:?} instead of {:?}", quote, expected_quote));
+        } else if dialect.escape != expected.escape {
+            differences.push(format!("escape {:?} instead of {:?}", dialect.escape, expected.escape));
+        }
+    }
+    if dialect.line_terminator != expected.line_terminator {
+        differences.push(format!(
+            "line terminator {} instead of {}",
+            dialect.line_terminator, expected.line_terminator
+        ));
+    }
+    (!differences.is_empty()).then(|| differences.join(", "))
+}
+
+/// Describes a partition while it is validated; it reports no issues of its own, except for key columns
+/// the partition doesn't have.
+#[derive(Clone)]
+struct PartitionCollector {
+    index: usize,
+    name: String,
+    dialect: Option<Dialect>,
+    common: CommonConfig,
+    key_columns: KeyColumns,
+    severity: Severity,
+    header: Option<Vec<String>>,
+    mem_limit: usize,
+    partitions: Arc<Mutex<BTreeMap<usize, Partition>>>,
+    keys: Arc<Mutex<Option<KeyStore>>>,
+}
+
+struct PartitionAcc {
+    separator: String,
+    quote_char: char,
+    skip_header: bool,
+    /// Row and number of fields of the first record seen.
+    first: Option<(usize, usize)>,
+    rows: usize,
+    keys: KeyStore,
+}
+
+impl Accumulator for PartitionAcc {
+    fn accumulate(&mut self, _input: &str, fields: &Fields, row: usize) {
+        let fields = fields.split_as(&self.separator, Some(self.quote_char));
+        if self.first.is_none_or(|(first, _)| row < first) {
+            self.first = Some((row, fields.len()));
+        }
+        if self.skip_header && row == 1 {
+            return;
+        }
+        self.rows += 1;
+        self.keys.add(&fields, row);
+    }
+
+    fn merge(&mut self, other: Box<dyn Accumulator>) {
+        let other = downcast_accumulator::<PartitionAcc>(other);
+        if let Some((row, _)) = other.first
+            && self.first.is_none_or(|(first, _)| row < first)
+        {
+            self.first = other.first;
+        }
+        self.rows += other.rows;
+        self.keys.append(other.keys);
+    }
+}
+
+impl Validator for PartitionCollector {
+    fn name(&self) -> &'static str {
+        "dataset"
+    }
+
+    fn validate<'a>(&self, input: &'a str, _fields: &Fields<'a>, _row: usize) -> ValidationResult<'a> {
+        ValidationResult::new(input)
+    }
+
+    fn policy(&self) -> Policy {
+        Policy {
+            severity: self.severity,
+            ..Policy::default()
+        }
+    }
+
+    fn accumulator(&self) -> Option<Box<dyn Accumulator>> {
+        // key columns that aren't found are reported by `finalize`
+        let key_positions = self.key_columns.positions().unwrap_or_default();
+        let keys = KeyStore::new(&self.common, key_positions, true, self.mem_limit).partition(self.index);
+        Some(Box::new(PartitionAcc {
+            separator: self.common.separator.clone().unwrap_or_else(|| ",".to_string()),
+            quote_char: self.common.quote_char(),
+            skip_header: self.common.has_header(),
+            first: None,
+            rows: 0,
+            keys,
+        }))
+    }
+
+    fn finalize(&self, acc: Option<Box<dyn Accumulator>>) -> Vec<ValidationIssue> {
+        let acc = acc.map(downcast_accumulator::<PartitionAcc>);
+        let partition = Partition {
+            name: self.name.clone(),
+            dialect: self.dialect.clone(),
+            header: self.header.clone(),
+            field_count: acc.as_ref().and_then(|acc| acc.first).map(|(_, fields)| fields),
+            rows: acc.as_ref().map_or(0, |acc| acc.rows),
+        };
+        self.partitions.lock().unwrap_or_else(|e| e.into_inner()).insert(self.index, partition);
+        if let Some(acc) = acc {
+            let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+            match keys.as_mut() {
+                Some(all) => all.append(acc.keys),
+                None => *keys = Some(acc.keys),
+            }
+        }
+
+        self.key_columns
+            .missing()
+            .map(|column| ValidationIssue::new(self.name(), 0, format!("Key column '{}' not found", column)))
+            .collect()
+    }
+
+    fn uses_memory(&self) -> bool {
+        !self.key_columns.is_empty()
+    }
+
+    fn bind_mem_limit(&mut self, mem_limit_bytes: usize) {
+        self.mem_limit = mem_limit_bytes;
+    }
+
+    fn bind_header(&mut self, header: &[String]) {
+        self.key_columns.bind_header(header);
+        self.header = Some(header.to_vec());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Limits, Sinks, process_input, thread_pool};
+    use crate::issue_log::IssueStorage;
+    use crate::utils::csv_utils::sniff_dialect;
+    use std::io;
+
+    /// Validate the partitions with their collectors only, and finish the dataset.
+    fn check(cfg: DatasetConfig, has_header: bool, partitions: &[&str]) -> Vec<(Option<String>, usize, String)> {
+        check_with_memory(cfg, has_header, partitions, 8)
+    }
+
+    fn check_with_memory(
+        cfg: DatasetConfig,
+        has_header: bool,
+        partitions: &[&str],
+        mem_limit: usize,
+    ) -> Vec<(Option<String>, usize, String)> {
+        let dataset = Dataset::new(cfg);
+        let pool = thread_pool(2).unwrap();
+        for (index, data) in partitions.iter().enumerate() {
+            let dialect = sniff_dialect(data);
+            let common = CommonConfig {
+                separator: Some(dialect.separator.clone()),
+                has_header: Some(has_header),
+                ..CommonConfig::default()
+            };
+            let name = format!("p{}", index);
+            let mut validators = vec![dataset.partition_validator(index, name, Some(dialect), &common)];
+            let mut sinks = Sinks {
+                output: Box::new(io::sink()),
+                rejects: None,
+                reject_reason: false,
+                issues: IssueStorage::default(),
+            };
+            let limits = Limits::default();
+            let summary =
+                process_input(data.as_bytes(), &mut validators, &mut sinks, &common, &limits, mem_limit, &pool);
+            let summary = summary.unwrap();
+            assert!(summary.issues.is_empty(), "{:?}", summary.issues.in_memory());
+        }
+        dataset
+            .finish()
+            .into_iter()
+            .map(|issue| (issue.file, issue.line_number, issue.message))
+            .collect()
+    }
+
+    fn issue(file: &str, row: usize, message: &str) -> (Option<String>, usize, String) {
+        (Some(file.to_string()), row, message.to_string())
+    }
+
+    #[test]
+    fn test_consistent_partitions() {
+        let cfg = DatasetConfig {
+            unique: vec!["id".to_string(), "day".to_string()],
+            min_rows: Some(4),
+            ..DatasetConfig::default()
+        };
+        let partitions = ["id;day;n\n1;mon;a\n2;mon;b\n", "id;day;n\n1;tue;c\n2;tue;\"d\"\n"];
+        assert!(check(cfg, true, &partitions).is_empty());
+    }
+
+    #[test]
+    fn test_inconsistent_partitions() {
+        let cfg = DatasetConfig {
+            unique: vec!["id".to_string()],
+            max_rows: Some(3),
+            ..DatasetConfig::default()
+        };
+        let partitions = ["id;n\n1;a\n2;b\n", "id;m\n3;c\n2;d\n", "id,n,x\n4,e,f\n"];
+
+        assert_eq!(
+            check(cfg, true, &partitions),
+            [
+                issue("p1", 0, r#"Header ["id", "m"] differs from the header of p0: ["id", "n"]"#),
+                issue("p2", 0, r#"Header ["id", "n", "x"] differs from the header of p0: ["id", "n"]"#),
+                issue("p2", 0, r#"Dialect differs from the one of p0: separator "," instead of ";""#),
+                issue("p2", 0, "3 fields, where p0 has 2"),
+                issue("p1", 3, "Key 2 on row 3 is also in p0, on row 3"),
+                (None, 0, "Expected at most 3 records in the dataset, found 5".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keys_by_position() {
+        let cfg = DatasetConfig {
+            unique: vec!["2".to_string()],
+            ..DatasetConfig::default()
+        };
+        // without a header every record is a data record, and keys are found in any batch
+        let partitions = ["a;x\nb;y\nc;z\n", "d;w\ne;z\nf;x\n"];
+        assert_eq!(
+            check(cfg, false, &partitions),
+            [
+                issue("p1", 2, "Key z on row 2 is also in p0, on row 3"),
+                issue("p1", 3, "Key x on row 3 is also in p0, on row 1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keys_spilled() {
+        let cfg = || DatasetConfig {
+            unique: vec!["id".to_string()],
+            ..DatasetConfig::default()
+        };
+        // keys repeated within a partition and across them
+        let partitions: Vec<String> = (0..3)
+            .map(|p| (0..100).map(|i| format!("{};{}\n", (i * 7 + p * 40) % 60, i)).collect::<String>())
+            .map(|rows| format!("id;n\n{}", rows))
+            .collect();
+        let partitions: Vec<&str> = partitions.iter().map(String::as_str).collect();
+
+        let in_memory = check_with_memory(cfg(), true, &partitions, usize::MAX);
+        assert!(!in_memory.is_empty());
+        assert_eq!(check_with_memory(cfg(), true, &partitions, 8), in_memory);
+    }
+}
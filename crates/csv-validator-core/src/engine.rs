@@ -0,0 +1,970 @@
+//! The streaming validation engine: reads records in batches, runs the validators on every row
+//! in parallel, writes the rows to their sinks in input order and collects the issues.
+//! Reading, validating and writing run concurrently, on successive batches.
+use std::borrow::Cow;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+pub use rayon::ThreadPool;
+use rayon::ThreadPoolBuilder;
+use rayon::prelude::*;
+
+use crate::config::CommonConfig;
+use crate::issue_log::{IssueLog, IssueStorage};
+use crate::readers::csv_readers::{Record, RecordReader};
+use crate::readers::mmap::{Chunk, split_chunks};
+use crate::utils::csv_utils::line_processor;
+use crate::utils::tokenizer::{Fields, RecordEnds};
+use crate::validators::issue::{ErrorPolicy, Severity, ValidationIssue, ValidationResult};
+use crate::validators::{Accumulator, RowFilter, Validator};
+
+fn new_accumulators(validators: &[Box<dyn Validator>]) -> Vec<Option<Box<dyn Accumulator>>> {
+    validators.iter().map(|v| v.accumulator()).collect()
+}
+
+fn merge_accumulators(
+    into: &mut [Option<Box<dyn Accumulator>>],
+    from: Vec<Option<Box<dyn Accumulator>>>,
+) {
+    for (acc, other) in into.iter_mut().zip(from) {
+        if let (Some(acc), Some(other)) = (acc, other) {
+            acc.merge(other);
+        }
+    }
+}
+
+/// Destinations for the validated rows: valid and fixed rows go to `output`,
+/// rows with issues that could not be fixed go to `rejects` (or nowhere).
+/// The writers can be borrowed, to finish them once the input is processed.
+pub struct Sinks<'a> {
+    pub output: Box<dyn Write + Send + 'a>,
+    pub rejects: Option<Box<dyn Write + Send + 'a>>,
+    /// Append a column with the issue messages to every rejected row.
+    pub reject_reason: bool,
+    /// How many issues are kept in memory, and what happens to the others.
+    pub issues: IssueStorage,
+}
+
+impl Sinks<'_> {
+    fn write_row(&mut self, row: usize, line: &str, reasons: Option<&str>, common: &CommonConfig) -> io::Result<()> {
+        let is_header = common.has_header() && row == 0;
+
+        match (reasons, &mut self.rejects) {
+            (None, rejects) => {
+                writeln!(self.output, "{}", line)?;
+                // rejects get the same header as the output, so both files load the same way
+                if let (true, Some(rejects)) = (is_header, rejects) {
+                    if self.reject_reason {
+                        writeln!(rejects, "{}", append_field(line, "errors", common))?;
+                    } else {
+                        writeln!(rejects, "{}", line)?;
+                    }
+                }
+            }
+            (Some(reasons), Some(rejects)) => {
+                if self.reject_reason {
+                    writeln!(rejects, "{}", append_field(line, reasons, common))?;
+                } else {
+                    writeln!(rejects, "{}", line)?;
+                }
+            }
+            (Some(_), None) => {}
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()?;
+        if let Some(rejects) = &mut self.rejects {
+            rejects.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// A validated row: the fixed line if it was fixed, its issues and, for rejected rows,
+/// the issues that could not be fixed.
+struct RowOutcome {
+    fixed: Option<String>,
+    issues: Vec<ValidationIssue>,
+    reasons: Option<String>,
+    errors: usize,
+    /// Set when a validator with the `abort` policy found an issue in this row.
+    abort: Option<String>,
+    /// Set when a row filter left the row out.
+    removed: bool,
+    /// The row digests for the row filters, by validator index.
+    digests: Vec<(usize, u128)>,
+}
+
+impl RowOutcome {
+    /// Add the issues of the row filters, with the policies of their validators.
+    fn add_filtered(&mut self, filtered: Vec<(ValidationIssue, ErrorPolicy)>) {
+        for (issue, on_error) in filtered {
+            self.errors += usize::from(issue.severity >= Severity::Error);
+            if issue.fixed {
+                self.removed = true;
+            } else {
+                match on_error {
+                    ErrorPolicy::Report => {}
+                    ErrorPolicy::Abort => {
+                        let abort = format!("aborted at row {}: {}", issue.line_number, issue.message);
+                        self.abort.get_or_insert(abort);
+                    }
+                    ErrorPolicy::Fix | ErrorPolicy::Drop => {
+                        self.reasons = Some(match self.reasons.take() {
+                            Some(reasons) => format!("{}; {}", reasons, issue.message),
+                            None => issue.message.clone(),
+                        });
+                    }
+                }
+            }
+            self.issues.push(issue);
+        }
+    }
+}
+
+/// Thresholds to stop validating early, checked after every row, in input order.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Limits {
+    pub max_errors: Option<usize>,
+    pub max_error_rate: Option<f64>,
+}
+
+impl Limits {
+    /// Whether the rows so far exceed a threshold. The error rate is of the `data_rows` (the header left out),
+    /// and is only checked once a single error in as many rows is within it, or at the end of the input.
+    fn exceeded(&self, stats: &Stats, data_rows: usize, end: bool) -> Option<String> {
+        if let Some(max) = self.max_errors
+            && stats.errors > max
+        {
+            return Some(format!("{} errors found, more than the maximum of {}", stats.errors, max));
+        }
+        if let Some(max_rate) = self.max_error_rate
+            && (end || data_rows as f64 * max_rate >= 1.0)
+        {
+            let rate = stats.error_rows as f64 / data_rows.max(1) as f64;
+            if rate > max_rate {
+                return Some(format!("error rate {:.4} exceeds the maximum of {}", rate, max_rate));
+            }
+        }
+        None
+    }
+}
+
+/// Counts of rows and errors (issues of severity error or worse).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub rows: usize,
+    pub errors: usize,
+    pub error_rows: usize,
+}
+
+pub struct Summary {
+    pub issues: IssueLog,
+    pub stats: Stats,
+    /// Why validation stopped before the end of the input, if it did.
+    pub aborted: Option<String>,
+}
+
+impl Summary {
+    /// The most severe issue found, if any.
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.issues.max_severity()
+    }
+}
+
+/// Append a quoted field to a line.
+fn append_field(line: &str, value: &str, common: &CommonConfig) -> String {
+    let sep = common.separator.as_deref().unwrap_or(",");
+    let quote = common.quote_char().to_string();
+    let escaped = value.replace(&quote, &quote.repeat(2));
+    format!("{}{}{}{}{}", line, sep, quote, escaped, quote)
+}
+
+/// Batches in memory at once: one being read, one validated and one written, plus one waiting
+/// in each of the channels between these stages.
+const BATCHES_IN_FLIGHT: usize = 5;
+
+/// Bind the header to the validators that match columns by name.
+fn bind_header(validators: &mut [Box<dyn Validator>], common: &CommonConfig, record: &str) {
+    let sep = common.separator.as_deref().unwrap_or(",");
+    if let Ok(header) = line_processor(record, sep, Some(common.quote_char())) {
+        for v in validators.iter_mut() {
+            v.bind_header(&header);
+        }
+    }
+}
+
+/// Share the memory limit between the batches in flight and the validators whose state grows with
+/// the input: when there are any, they share half of it. Returns the memory for the batches.
+fn bind_mem_limit(validators: &mut [Box<dyn Validator>], mem_limit_bytes: usize) -> usize {
+    let using = validators.iter().filter(|v| v.uses_memory()).count();
+    if using == 0 {
+        return mem_limit_bytes;
+    }
+    for v in validators.iter_mut().filter(|v| v.uses_memory()) {
+        v.bind_mem_limit(mem_limit_bytes / 2 / using);
+    }
+    mem_limit_bytes / 2
+}
+
+/// The pool of `threads` threads to validate on; it is built once and shared by all inputs.
+pub fn thread_pool(threads: usize) -> io::Result<ThreadPool> {
+    ThreadPoolBuilder::new().num_threads(threads).build().map_err(io::Error::other)
+}
+
+/// Validate all records of `reader` and write them to `sinks`.
+///
+/// Reading, validating and writing overlap: the records are read on the calling thread and collected
+/// into batches, the rows of a batch are validated on the `pool` (see [`thread_pool`]), and a writer
+/// thread writes the rows in input order. Batches are sized so that all batches in flight take about
+/// `mem_limit_bytes`, or half of it when validators keep state that grows with the input, like the keys
+/// seen; these share the other half. File-level issues are reported once the input is exhausted.
+pub fn process_input<R: BufRead>(
+    reader: R,
+    validators: &mut [Box<dyn Validator>],
+    sinks: &mut Sinks<'_>,
+    common: &CommonConfig,
+    limits: &Limits,
+    mem_limit_bytes: usize,
+    pool: &ThreadPool,
+) -> io::Result<Summary> {
+    let mut records = record_reader(reader, common).enumerate().peekable();
+    if common.has_header()
+        && let Some((_, Ok(record))) = records.peek()
+    {
+        bind_header(validators, common, &record.text);
+    }
+    let mem_limit_bytes = bind_mem_limit(validators, mem_limit_bytes);
+
+    let validators: &[Box<dyn Validator>] = validators;
+    let batch_bytes = (mem_limit_bytes / BATCHES_IN_FLIGHT).max(1);
+
+    run_pipeline(validators, sinks, common, limits, pool, |send| {
+        let mut batch = Vec::new();
+        let mut total_bytes = 0;
+        // records rather than lines: quoted fields may contain line breaks
+        for (row, record) in records {
+            let row = batch_row(row, record?, validators)?;
+            total_bytes += row.line.len();
+            batch.push(row);
+
+            if total_bytes >= batch_bytes {
+                if !send(std::mem::take(&mut batch)) {
+                    return Ok(());
+                }
+                total_bytes = 0;
+            }
+        }
+        if !batch.is_empty() {
+            send(batch);
+        }
+        Ok(())
+    })
+}
+
+/// Where the records of the input end, as configured.
+fn record_ends(common: &CommonConfig) -> RecordEnds {
+    let separator = common.separator.as_deref().unwrap_or(",");
+    RecordEnds::new(Some(separator), Some(common.quote_char())).line_terminator(common.line_terminator())
+}
+
+/// The records of the input, as configured.
+fn record_reader<R: BufRead>(reader: R, common: &CommonConfig) -> RecordReader<R> {
+    let separator = common.separator.as_deref().unwrap_or(",");
+    RecordReader::new(reader, Some(separator), Some(common.quote_char())).line_terminator(common.line_terminator())
+}
+
+/// Validate all records of an input mapped in memory, like [`process_input`], but read in parallel:
+/// the input is split into chunks at record boundaries, and the chunks are read on the pool as well,
+/// as many at a time as there are threads. Row numbers are those in the whole input.
+pub fn process_mapped(
+    data: &[u8],
+    validators: &mut [Box<dyn Validator>],
+    sinks: &mut Sinks<'_>,
+    common: &CommonConfig,
+    limits: &Limits,
+    mem_limit_bytes: usize,
+    pool: &ThreadPool,
+) -> io::Result<Summary> {
+    if common.has_header()
+        && let Some(Ok(record)) = record_reader(data, common).next()
+    {
+        bind_header(validators, common, &record.text);
+    }
+    let mem_limit_bytes = bind_mem_limit(validators, mem_limit_bytes);
+
+    let validators: &[Box<dyn Validator>] = validators;
+    // the chunks read at once take the place of the single batch being read
+    let chunk_bytes = (mem_limit_bytes / (BATCHES_IN_FLIGHT - 1 + pool.current_num_threads())).max(1);
+
+    run_pipeline(validators, sinks, common, limits, pool, |send| {
+        // the chunks are split on a thread of their own, so the first ones are read while later ones are split;
+        // dropping the receiver stops the splitting. The splitting runs on the pool, which it must never block
+        // waiting for the reading: the channel is unbounded, and only holds the ranges of the chunks.
+        let (chunk_tx, chunk_rx) = mpsc::channel();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                let ends = record_ends(common);
+                pool.install(|| split_chunks(data, &ends, chunk_bytes, |chunk| chunk_tx.send(chunk).is_ok()));
+            });
+            read_chunks(data, chunk_rx, validators, common, pool, send)
+        })
+    })
+}
+
+/// Read the records of the chunks received, as many chunks at a time as there are threads, and send them
+/// as batches, in input order.
+fn read_chunks(
+    data: &[u8],
+    chunks: Receiver<Chunk>,
+    validators: &[Box<dyn Validator>],
+    common: &CommonConfig,
+    pool: &ThreadPool,
+    send: &mut dyn FnMut(Vec<BatchRow>) -> bool,
+) -> io::Result<()> {
+    loop {
+        let group: Vec<Chunk> = chunks.iter().take(pool.current_num_threads()).collect();
+        if group.is_empty() {
+            return Ok(());
+        }
+        let batches: Vec<io::Result<Vec<BatchRow>>> = pool.install(|| {
+            group
+                .par_iter()
+                .map(|chunk| {
+                    record_reader(&data[chunk.range.clone()], common)
+                        .enumerate()
+                        .map(|(i, record)| {
+                            let mut record = record?;
+                            record.start_line += chunk.first_line;
+                            record.end_line += chunk.first_line;
+                            batch_row(chunk.first_row + i, record, validators)
+                        })
+                        .collect()
+                })
+                .collect()
+        });
+        for batch in batches {
+            if !send(batch?) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Run the pipeline: `read` sends the batches on the calling thread, in input order, until `send`
+/// returns false because validation stopped. The batches are validated on the `pool`, which `read` may
+/// use as well, and written by a writer thread.
+fn run_pipeline<F>(
+    validators: &[Box<dyn Validator>],
+    sinks: &mut Sinks<'_>,
+    common: &CommonConfig,
+    limits: &Limits,
+    pool: &ThreadPool,
+    read: F,
+) -> io::Result<Summary>
+where
+    F: FnOnce(&mut dyn FnMut(Vec<BatchRow>) -> bool) -> io::Result<()>,
+{
+    let fix_enabled = validators.iter().any(|v| v.should_fix());
+
+    // a stage that stops early drops its receiver, which stops the stages before it
+    let (batch_tx, batch_rx) = mpsc::sync_channel::<Vec<BatchRow>>(1);
+    let (result_tx, result_rx) = mpsc::sync_channel::<BatchResult>(1);
+
+    let (read, written) = thread::scope(|scope| {
+        scope.spawn(move || {
+            for rows in batch_rx {
+                let result = validate_batch(rows, validators, fix_enabled, common, pool);
+                if result_tx.send(result).is_err() {
+                    break;
+                }
+            }
+        });
+        let writer = scope.spawn(move || write_batches(result_rx, validators, sinks, common, limits));
+
+        let read = read(&mut |batch| batch_tx.send(batch).is_ok());
+        drop(batch_tx);
+
+        let written = writer.join().unwrap_or_else(|e| std::panic::resume_unwind(e));
+        (read, written)
+    });
+    let Written { accumulators, mut stats, mut issues, aborted } = written?;
+    read?;
+
+    // file-level checks are meaningless on a partially read file
+    if aborted.is_none() {
+        for (v, acc) in validators.iter().zip(accumulators) {
+            for mut issue in v.finalize(acc) {
+                issue.severity = v.policy().severity;
+                if issue.severity >= Severity::Error {
+                    stats.errors += 1;
+                }
+                issues.push(issue)?;
+            }
+        }
+    }
+
+    Ok(Summary { issues, stats, aborted })
+}
+
+/// Run the byte-level checks on a row that isn't valid UTF-8; rows no validator checks can't be validated.
+/// Returns the results by validator index.
+fn check_bytes(
+    raw: &[u8],
+    validators: &[Box<dyn Validator>],
+    row: usize,
+) -> io::Result<Vec<(usize, ValidationResult<'static>)>> {
+    let results: Vec<_> = validators
+        .iter()
+        .enumerate()
+        .filter_map(|(i, v)| v.validate_bytes(raw, row + 1).map(|result| (i, result)))
+        .collect();
+
+    if results.is_empty() {
+        let position = std::str::from_utf8(raw).err().map_or(0, |e| e.valid_up_to());
+        let message = format!(
+            "row {} is not valid UTF-8 (byte {}); set the input encoding, or add the utf8 validator",
+            row + 1,
+            position
+        );
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(results)
+}
+
+/// A row to validate, with the results of the byte-level checks if it isn't valid UTF-8.
+struct BatchRow {
+    row: usize,
+    /// The physical lines the row was read from.
+    lines: (usize, usize),
+    line: String,
+    checked_bytes: Vec<(usize, ValidationResult<'static>)>,
+}
+
+fn batch_row(row: usize, record: Record, validators: &[Box<dyn Validator>]) -> io::Result<BatchRow> {
+    let checked_bytes = match &record.raw {
+        Some(raw) => check_bytes(raw, validators, row)?,
+        None => Vec::new(),
+    };
+    let lines = (record.start_line, record.end_line);
+    Ok(BatchRow { row, lines, line: record.text, checked_bytes })
+}
+
+/// A validated batch: the rows as read, and what the validators made of them.
+struct BatchResult {
+    rows: Vec<BatchRow>,
+    outcomes: Vec<RowOutcome>,
+    accumulators: Vec<Option<Box<dyn Accumulator>>>,
+}
+
+/// What the writer collected from the validated batches.
+struct Written {
+    accumulators: Vec<Option<Box<dyn Accumulator>>>,
+    stats: Stats,
+    issues: IssueLog,
+    /// Why validation stopped before the end of the input, if it did.
+    aborted: Option<String>,
+}
+
+/// What to do with the rows a validator reports: the `fix` policy of a validator that has no fix reports.
+fn error_policy(validator: &dyn Validator) -> ErrorPolicy {
+    match validator.policy().on_error {
+        ErrorPolicy::Fix if !validator.should_fix() => ErrorPolicy::Report,
+        on_error => on_error,
+    }
+}
+
+/// Validate a single row, and add it to the accumulators of the worker. The row is only copied when a
+/// validator fixes it.
+fn validate_row(
+    batch_row: &BatchRow,
+    validators: &[Box<dyn Validator>],
+    fix_enabled: bool,
+    common: &CommonConfig,
+    accumulators: &mut [Option<Box<dyn Accumulator>>],
+) -> RowOutcome {
+    let row = batch_row.row;
+    let mut fixed = Cow::Borrowed(batch_row.line.as_str());
+    let mut row_issues = Vec::new();
+
+    // the policy of the validator that reported each issue
+    let mut on_error = Vec::new();
+    let mut collect = |v: &dyn Validator, found: Vec<ValidationIssue>| {
+        let policy = v.policy();
+        for mut issue in found {
+            issue.severity = policy.severity;
+            on_error.push(error_policy(v));
+            row_issues.push(issue);
+        }
+    };
+
+    for (i, updated) in batch_row.checked_bytes.iter().cloned() {
+        collect(validators[i].as_ref(), updated.issues);
+        if updated.modified {
+            fixed = updated.fixed;
+        }
+    }
+
+    // the row is split once for all validators, and again only when a fix rewrites it
+    let (separator, quote_char) = (common.separator.as_deref().unwrap_or(","), Some(common.quote_char()));
+    let mut fields = Fields::new(&fixed, separator, quote_char);
+    // rows are validated in parallel, so the validators of a row run one after the other
+    for v in validators.iter() {
+        let updated = v.validate(&fixed, &fields, row + 1);
+        collect(v.as_ref(), updated.issues);
+        // check only: the rows the validators return are left unused
+        if fix_enabled && updated.modified {
+            fixed = Cow::Owned(updated.fixed.into_owned());
+            fields = Fields::new(&fixed, separator, quote_char);
+        }
+    }
+
+    // issues of validators with the `report` policy don't reject the row
+    let unfixed: Vec<(&ValidationIssue, ErrorPolicy)> = row_issues
+        .iter()
+        .zip(on_error)
+        .filter(|(issue, on_error)| !issue.fixed && *on_error != ErrorPolicy::Report)
+        .collect();
+    let reasons = (!unfixed.is_empty()).then(|| {
+        unfixed
+            .iter()
+            .map(|(issue, _)| issue.message.as_str())
+            .collect::<Vec<_>>()
+            .join("; ")
+    });
+    let abort = unfixed
+        .iter()
+        .find(|(_, on_error)| *on_error == ErrorPolicy::Abort)
+        .map(|(issue, _)| format!("aborted at row {}: {}", issue.line_number, issue.message));
+    let errors = row_issues
+        .iter()
+        .filter(|issue| issue.severity >= Severity::Error)
+        .count();
+    // the row filters run on the writer thread; what they can do without the rows before is done here
+    let digests = validators
+        .iter()
+        .enumerate()
+        .filter_map(|(i, v)| Some((i, v.row_digest(&fixed, &fields)?)))
+        .collect();
+    for acc in accumulators.iter_mut().flatten() {
+        acc.accumulate(&fixed, &fields, row + 1);
+    }
+
+    RowOutcome {
+        fixed: match fixed {
+            Cow::Owned(line) => Some(line),
+            Cow::Borrowed(_) => None,
+        },
+        issues: row_issues,
+        reasons,
+        errors,
+        abort,
+        removed: false,
+        digests,
+    }
+}
+
+/// Validate the rows of a batch on the pool.
+fn validate_batch(
+    rows: Vec<BatchRow>,
+    validators: &[Box<dyn Validator>],
+    fix_enabled: bool,
+    common: &CommonConfig,
+    pool: &ThreadPool,
+) -> BatchResult {
+    // every worker folds its rows into its own accumulators; these are merged pairwise,
+    // which keeps the outcomes in row order
+    let (outcomes, accumulators) = pool.install(|| {
+        rows.par_iter()
+            .fold(
+                || (Vec::new(), new_accumulators(validators)),
+                |(mut outcomes, mut accumulators), batch_row| {
+                    outcomes.push(validate_row(batch_row, validators, fix_enabled, common, &mut accumulators));
+                    (outcomes, accumulators)
+                },
+            )
+            .reduce(
+                || (Vec::new(), new_accumulators(validators)),
+                |(mut outcomes, mut accumulators), (other_outcomes, other_accumulators)| {
+                    outcomes.extend(other_outcomes);
+                    merge_accumulators(&mut accumulators, other_accumulators);
+                    (outcomes, accumulators)
+                },
+            )
+    });
+
+    BatchResult { rows, outcomes, accumulators }
+}
+
+/// Run the row filters on a row, in input order.
+fn filter_row(
+    filters: &mut [Option<Box<dyn RowFilter>>],
+    validators: &[Box<dyn Validator>],
+    line: &str,
+    row: usize,
+    digests: &[(usize, u128)],
+) -> Vec<(ValidationIssue, ErrorPolicy)> {
+    let mut filtered = Vec::new();
+    for (i, (v, filter)) in validators.iter().zip(filters.iter_mut()).enumerate() {
+        let Some(filter) = filter else { continue };
+        let policy = v.policy();
+        let digest = digests.iter().find(|(j, _)| *j == i).map(|&(_, digest)| digest);
+        for mut issue in filter.check(line, row + 1, digest) {
+            issue.severity = policy.severity;
+            filtered.push((issue, error_policy(v.as_ref())));
+        }
+    }
+    filtered
+}
+
+/// Write the validated batches in input order, and collect their issues; rows are filtered on the way.
+/// Stops at a row with an `abort` policy issue, or after the batch that exceeds the `limits`.
+fn write_batches(
+    results: Receiver<BatchResult>,
+    validators: &[Box<dyn Validator>],
+    sinks: &mut Sinks<'_>,
+    common: &CommonConfig,
+    limits: &Limits,
+) -> io::Result<Written> {
+    let mut accumulators = new_accumulators(validators);
+    let mut filters: Vec<_> = validators.iter().map(|v| v.row_filter()).collect();
+    let mut stats = Stats::default();
+    let mut issues = IssueLog::new(sinks.issues);
+    let mut aborted = None;
+
+    let mut header_rows = 0;
+
+    'batches: for result in results {
+        merge_accumulators(&mut accumulators, result.accumulators);
+        for (batch_row, mut outcome) in result.rows.iter().zip(result.outcomes) {
+            let line = outcome.fixed.as_deref().unwrap_or(&batch_row.line);
+            let filtered = filter_row(&mut filters, validators, line, batch_row.row, &outcome.digests);
+            outcome.add_filtered(filtered);
+
+            stats.rows += 1;
+            stats.errors += outcome.errors;
+            stats.error_rows += usize::from(outcome.errors > 0);
+            header_rows += usize::from(common.has_header() && batch_row.row == 0);
+            for issue in &mut outcome.issues {
+                (issue.start_line, issue.end_line) = (Some(batch_row.lines.0), Some(batch_row.lines.1));
+            }
+            issues.extend(outcome.issues)?;
+            // nothing is written from the offending row on
+            if outcome.abort.is_some() {
+                aborted = outcome.abort;
+                break 'batches;
+            }
+            if !outcome.removed {
+                let line = outcome.fixed.as_deref().unwrap_or(&batch_row.line);
+                sinks.write_row(batch_row.row, line, outcome.reasons.as_deref(), common)?;
+            }
+            // the rows validated ahead of this one are left out
+            aborted = limits.exceeded(&stats, stats.rows - header_rows, false);
+            if aborted.is_some() {
+                break 'batches;
+            }
+        }
+    }
+    if aborted.is_none() {
+        aborted = limits.exceeded(&stats, stats.rows - header_rows, true);
+    }
+
+    for (acc, filter) in accumulators.iter_mut().zip(filters) {
+        if let Some(state) = filter.and_then(|filter| filter.finish()) {
+            *acc = Some(state);
+        }
+    }
+
+    sinks.flush()?;
+    Ok(Written { accumulators, stats, issues, aborted })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    use crate::validators::file_validators::{DuplicateRows, DuplicateRowsConfig};
+    use crate::validators::issue::Policy;
+    use crate::validators::line_validators::{
+        FieldCount, FieldCountConfig, IllegalChars, IllegalCharsConfig, Utf8, Utf8Config,
+    };
+
+    /// A writer the test can still read from once it's been handed to the sinks.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn illegal_chars(policy: Policy) -> Vec<Box<dyn Validator>> {
+        vec![Box::new(IllegalChars::new(IllegalCharsConfig {
+            illegal_chars: vec!["!".to_string()],
+            replace_with: Vec::new(),
+            common: CommonConfig::default(),
+            policy,
+        }))]
+    }
+
+    fn sinks() -> (Sinks<'static>, SharedBuffer, SharedBuffer) {
+        let (output, rejects) = (SharedBuffer::default(), SharedBuffer::default());
+        let sinks = Sinks {
+            output: Box::new(output.clone()),
+            rejects: Some(Box::new(rejects.clone())),
+            reject_reason: false,
+            issues: IssueStorage::default(),
+        };
+        (sinks, output, rejects)
+    }
+
+    fn run(validators: &mut [Box<dyn Validator>], input: &str, limits: &Limits) -> (Summary, String, String) {
+        // a tiny memory limit makes every row a batch of its own
+        run_with_memory(validators, input, limits, 1)
+    }
+
+    fn run_with_memory(
+        validators: &mut [Box<dyn Validator>],
+        input: &str,
+        limits: &Limits,
+        mem_limit: usize,
+    ) -> (Summary, String, String) {
+        let (mut sinks, output, rejects) = sinks();
+        let common = CommonConfig { has_header: Some(true), ..CommonConfig::default() };
+        let pool = thread_pool(2).unwrap();
+        let summary =
+            process_input(input.as_bytes(), validators, &mut sinks, &common, limits, mem_limit, &pool).unwrap();
+        (summary, output.contents(), rejects.contents())
+    }
+
+    #[test]
+    fn test_process_input() {
+        let input = "id,name\n1,a!\n2,b\n3,c!\n";
+        let (summary, output, rejects) = run(&mut illegal_chars(Policy::default()), input, &Limits::default());
+
+        assert_eq!(output, "id,name\n2,b\n");
+        assert_eq!(rejects, "id,name\n1,a!\n3,c!\n");
+        assert_eq!(summary.issues.in_memory().iter().map(|i| i.line_number).collect::<Vec<_>>(), [2, 4]);
+        assert_eq!(summary.stats.error_rows, 2);
+        assert_eq!(summary.max_severity(), Some(Severity::Error));
+        assert!(summary.aborted.is_none());
+    }
+
+    #[test]
+    fn test_process_input_invalid_utf8() {
+        let input: &[u8] = b"id,name\n1,Ren\xE9e\n2,b\n";
+        let common = CommonConfig { has_header: Some(true), ..CommonConfig::default() };
+        let pool = thread_pool(2).unwrap();
+        let process = |validators: &mut [Box<dyn Validator>]| {
+            let output = SharedBuffer::default();
+            let mut sinks = Sinks {
+                output: Box::new(output.clone()),
+                rejects: None,
+                reject_reason: false,
+                issues: IssueStorage::default(),
+            };
+            process_input(input, validators, &mut sinks, &common, &Limits::default(), 1, &pool)
+                .map(|summary| (summary, output.contents()))
+        };
+
+        // rows can't be read without a validator that checks bytes
+        let error = process(&mut illegal_chars(Policy::default())).err().unwrap();
+        assert!(error.to_string().starts_with("row 2 is not valid UTF-8 (byte 5)"));
+
+        let mut validators = illegal_chars(Policy::default());
+        validators.push(Box::new(Utf8::new(Utf8Config {
+            replace_with: Some('e'),
+            common: CommonConfig::default(),
+            policy: Policy { on_error: ErrorPolicy::Fix, ..Policy::default() },
+        })));
+        let (summary, output) = process(&mut validators).unwrap();
+        assert_eq!(output, "id,name\n1,Renee\n2,b\n");
+        assert_eq!((summary.issues.in_memory()[0].line_number, summary.issues.in_memory()[0].position), (2, Some(5)));
+    }
+
+    #[test]
+    fn test_process_input_policies() {
+        let report = Policy { severity: Severity::Warning, on_error: ErrorPolicy::Report };
+        let (summary, output, _) = run(&mut illegal_chars(report), "id\n1!\n2\n", &Limits::default());
+        assert_eq!(output, "id\n1!\n2\n");
+        assert_eq!(summary.max_severity(), Some(Severity::Warning));
+
+        let abort = Policy { on_error: ErrorPolicy::Abort, ..Policy::default() };
+        let (summary, output, _) = run(&mut illegal_chars(abort), "id\n1\n2!\n3!\n", &Limits::default());
+        assert_eq!(output, "id\n1\n");
+        assert_eq!(summary.issues.len(), 1);
+        assert!(summary.aborted.unwrap().starts_with("aborted at row 3"));
+
+        // the fix policy fixes the rows of validators that have a fix, and only reports for the others
+        let fix = Policy { on_error: ErrorPolicy::Fix, ..Policy::default() };
+        let mut validators = illegal_chars(fix);
+        validators.push(Box::new(FieldCount::new(FieldCountConfig {
+            expected: 1,
+            common: CommonConfig::default(),
+            policy: fix,
+        })));
+        let (summary, output, rejects) = run(&mut validators, "id\n1!\n2,3\n", &Limits::default());
+        assert_eq!((output.as_str(), rejects.as_str()), ("id\n1\n2,3\n", "id\n"));
+        assert_eq!(summary.issues.len(), 2);
+        assert!(summary.issues.in_memory()[0].fixed && !summary.issues.in_memory()[1].fixed);
+    }
+
+    #[test]
+    fn test_process_input_fields() {
+        // a row rewritten by a fix is split again for the validators after it
+        let field_count = |separator: &str| -> Box<dyn Validator> {
+            Box::new(FieldCount::new(FieldCountConfig {
+                expected: 2,
+                common: CommonConfig { separator: Some(separator.to_string()), ..CommonConfig::default() },
+                policy: Policy::default(),
+            }))
+        };
+        let mut validators: Vec<Box<dyn Validator>> = vec![
+            Box::new(IllegalChars::new(IllegalCharsConfig {
+                illegal_chars: vec![";".to_string()],
+                replace_with: vec![",".to_string()],
+                common: CommonConfig::default(),
+                policy: Policy { on_error: ErrorPolicy::Fix, ..Policy::default() },
+            })),
+            field_count(","),
+        ];
+        let (summary, output, _) = run(&mut validators, "id,name\n1;a\n", &Limits::default());
+        assert_eq!(output, "id,name\n1,a\n");
+        assert_eq!(summary.issues.len(), 1);
+        assert!(summary.issues.in_memory()[0].fixed);
+
+        // a validator with a separator of its own splits the rows itself
+        let (summary, _, _) = run(&mut [field_count("|")], "id|name\n1|a\n", &Limits::default());
+        assert_eq!(summary.issues.len(), 0);
+    }
+
+    #[test]
+    fn test_process_input_limits() {
+        // the rows validated ahead of the one that exceeds the limit are left out
+        let limits = Limits { max_errors: Some(1), ..Limits::default() };
+        let input = "id\n1!\n2!\n3!\n4!\n5!\n";
+        let (summary, output, rejects) = run(&mut illegal_chars(Policy::default()), input, &limits);
+
+        assert_eq!(output, "id\n");
+        assert_eq!(rejects, "id\n1!\n2!\n");
+        assert_eq!(summary.issues.len(), 2);
+        assert_eq!(summary.stats.rows, 3);
+        assert!(summary.aborted.unwrap().starts_with("2 errors found"));
+
+        // the limit is checked at the row that exceeds it, also in the middle of a batch
+        let in_one_batch = |input: &str, limits: &Limits| {
+            run_with_memory(&mut illegal_chars(Policy::default()), input, limits, 1 << 20)
+        };
+        let (summary, output, rejects) = in_one_batch(input, &limits);
+        assert_eq!((output.as_str(), rejects.as_str()), ("id\n", "id\n1!\n2!\n"));
+        assert_eq!(summary.stats.rows, 3);
+
+        // the rate is of the data rows: 1 in 4 is within 0.25, and only checked once there are 4 of them
+        let limits = Limits { max_error_rate: Some(0.25), ..Limits::default() };
+        let (summary, ..) = in_one_batch("id\n1!\n2\n3\n4\n", &limits);
+        assert!(summary.aborted.is_none());
+        let (summary, ..) = in_one_batch("id\n1!\n2!\n3\n4\n5\n", &limits);
+        assert!(summary.aborted.unwrap().starts_with("error rate 0.5000"));
+        assert_eq!(summary.stats.rows, 5);
+    }
+
+    #[test]
+    fn test_process_mapped() {
+        // the same rows, issues and row numbers as read from a stream, whatever the size of the chunks,
+        // with a quote in the middle of an unquoted field as well
+        let input = "id,note\n1,\"a!\nb\"\n2,5\" c\n3,d!\n4,e";
+        let (expected, expected_output, expected_rejects) =
+            run(&mut illegal_chars(Policy::default()), input, &Limits::default());
+        // the issues after a record that spans lines are on the lines of the input, not the rows
+        let issues = expected.issues.in_memory();
+        let lines: Vec<_> = issues.iter().map(|i| (i.line_number, i.start_line, i.end_line)).collect();
+        assert_eq!(lines, [(2, Some(2), Some(3)), (4, Some(5), Some(5))]);
+        let common = CommonConfig { has_header: Some(true), ..CommonConfig::default() };
+
+        // a single thread splits and reads the chunks too
+        for (threads, mem_limit) in [1, 2].into_iter().flat_map(|threads| [1, 64, 1024].map(|mem| (threads, mem))) {
+            let pool = thread_pool(threads).unwrap();
+            let (mut sinks, output, rejects) = sinks();
+            let mut validators = illegal_chars(Policy::default());
+            let limits = Limits::default();
+            let summary =
+                process_mapped(input.as_bytes(), &mut validators, &mut sinks, &common, &limits, mem_limit, &pool)
+                    .unwrap();
+
+            assert_eq!(output.contents(), expected_output);
+            assert_eq!(rejects.contents(), expected_rejects);
+            assert_eq!(summary.issues.in_memory(), expected.issues.in_memory());
+            assert_eq!(summary.stats.rows, 5);
+        }
+    }
+
+    #[test]
+    fn test_process_input_duplicate_rows() {
+        let duplicate_rows = |on_error: ErrorPolicy| -> Vec<Box<dyn Validator>> {
+            vec![Box::new(DuplicateRows::new(DuplicateRowsConfig {
+                trim: true,
+                ignore_case: false,
+                common: CommonConfig { has_header: Some(true), ..CommonConfig::default() },
+                policy: Policy { on_error, ..Policy::default() },
+            }))]
+        };
+        // rows are compared in input order, though they are hashed in batches of a few rows, in parallel;
+        // the validator gets half of the memory limit, and keeps half of that for 3 rows seen of 40 bytes
+        let input = "id,name\n1,a\n2,b\n1, a\n2,B\n1,a\n";
+        let run = |on_error: ErrorPolicy, mem_limit: usize| {
+            run_with_memory(&mut duplicate_rows(on_error), input, &Limits::default(), mem_limit)
+        };
+
+        let (summary, output, rejects) = run(ErrorPolicy::Fix, 480);
+        assert_eq!(output, "id,name\n1,a\n2,b\n2,B\n");
+        assert_eq!(rejects, "id,name\n");
+        let issues = summary.issues.in_memory();
+        assert_eq!(issues.iter().map(|i| i.message.as_str()).collect::<Vec<_>>(), [
+            "Row 4 is a duplicate of row 2",
+            "Row 6 is a duplicate of row 2"
+        ]);
+        assert!(issues.iter().all(|i| i.fixed));
+        assert_eq!(summary.stats.error_rows, 2);
+
+        let (_, output, rejects) = run(ErrorPolicy::Drop, 480);
+        assert_eq!(output, "id,name\n1,a\n2,b\n2,B\n");
+        assert_eq!(rejects, "id,name\n1, a\n1,a\n");
+
+        let (summary, output, _) = run(ErrorPolicy::Report, 480);
+        assert_eq!(output, input);
+        assert_eq!(summary.issues.len(), 2);
+
+        let (summary, output, _) = run(ErrorPolicy::Abort, 480);
+        assert_eq!(output, "id,name\n1,a\n2,b\n");
+        assert!(summary.aborted.unwrap().starts_with("aborted at row 4"));
+
+        // with memory for one row seen, the rows after it are spilled, and their duplicates are found at the end,
+        // once they have been written
+        let input = "id,name\n1,a\n2,b\n1, a\n2,B\n1,a\n2,b\n";
+        let (summary, output, _) =
+            run_with_memory(&mut duplicate_rows(ErrorPolicy::Fix), input, &Limits::default(), 160);
+        assert_eq!(output, "id,name\n1,a\n2,b\n2,B\n2,b\n");
+        let issues = summary.issues.in_memory();
+        assert_eq!(issues.iter().map(|i| (i.message.as_str(), i.fixed)).collect::<Vec<_>>(), [
+            ("Row 4 is a duplicate of row 2", true),
+            ("Row 6 is a duplicate of row 2", true),
+            ("Row 7 is a duplicate of row 3", false),
+        ]);
+        assert_eq!(summary.stats.errors, 3);
+    }
+}
@@ -0,0 +1,221 @@
+//! Where the issues found are kept until they are reported: in memory up to a limit, and beyond it
+//! in a temporary file or only as counts, so a badly broken file can't exhaust memory.
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::str::FromStr;
+
+use crate::utils::temp_file::TempFile;
+use crate::validators::issue::{Severity, ValidationIssue};
+
+/// Issues kept in memory unless configured otherwise.
+pub const DEFAULT_MAX_ISSUES_IN_MEMORY: usize = 100_000;
+
+/// What to do with the issues that don't fit in memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IssueMode {
+    /// Write them to a temporary file, so every issue is still reported.
+    #[default]
+    Spill,
+    /// Only count them, by validator and severity.
+    Summary,
+}
+
+impl FromStr for IssueMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "spill" => Ok(IssueMode::Spill),
+            "summary" => Ok(IssueMode::Summary),
+            _ => Err(format!("Unknown issue mode: '{}'", s)),
+        }
+    }
+}
+
+/// How many issues are kept in memory, and what happens to the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IssueStorage {
+    pub mode: IssueMode,
+    pub max_in_memory: usize,
+}
+
+impl Default for IssueStorage {
+    fn default() -> Self {
+        Self {
+            mode: IssueMode::default(),
+            max_in_memory: DEFAULT_MAX_ISSUES_IN_MEMORY,
+        }
+    }
+}
+
+/// A temporary file with the issues beyond the ones in memory, one json object per line.
+/// The file is removed when the log is dropped.
+struct SpillFile {
+    file: TempFile,
+    writer: BufWriter<File>,
+    count: usize,
+}
+
+impl SpillFile {
+    fn create() -> io::Result<Self> {
+        let (file, writer) = TempFile::create("issues", "jsonl")?;
+        Ok(Self { file, writer: BufWriter::new(writer), count: 0 })
+    }
+}
+
+/// The issues found, in the order they were found.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::issue_log::{IssueLog, IssueMode, IssueStorage};
+/// use csv_validator_core::validators::issue::{Severity, ValidationIssue};
+///
+/// let mut log = IssueLog::new(IssueStorage { mode: IssueMode::Summary, max_in_memory: 1 });
+/// for row in 1..=3 {
+///     log.push(ValidationIssue::new("field_count", row, format!("bad row {}", row))).unwrap();
+/// }
+///
+/// assert_eq!(log.len(), 3);
+/// assert_eq!(log.in_memory().len(), 1);
+/// assert_eq!(log.dropped(), 2);
+/// assert_eq!(log.counts().collect::<Vec<_>>(), [("field_count", Severity::Error, 3)]);
+/// ```
+pub struct IssueLog {
+    storage: IssueStorage,
+    issues: Vec<ValidationIssue>,
+    spill: Option<SpillFile>,
+    /// Number of issues by validator and severity, kept or not.
+    counts: BTreeMap<(String, Severity), usize>,
+    total: usize,
+}
+
+impl IssueLog {
+    pub fn new(storage: IssueStorage) -> Self {
+        Self {
+            storage,
+            issues: Vec::new(),
+            spill: None,
+            counts: BTreeMap::new(),
+            total: 0,
+        }
+    }
+
+    pub fn push(&mut self, issue: ValidationIssue) -> io::Result<()> {
+        *self.counts.entry((issue.validator.clone(), issue.severity)).or_insert(0) += 1;
+        self.total += 1;
+
+        if self.issues.len() < self.storage.max_in_memory {
+            self.issues.push(issue);
+            return Ok(());
+        }
+        match self.storage.mode {
+            IssueMode::Summary => Ok(()),
+            IssueMode::Spill => {
+                let spill = match &mut self.spill {
+                    Some(spill) => spill,
+                    None => self.spill.insert(SpillFile::create()?),
+                };
+                serde_json::to_writer(&mut spill.writer, &issue)?;
+                spill.writer.write_all(b"\n")?;
+                spill.count += 1;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn extend(&mut self, issues: impl IntoIterator<Item = ValidationIssue>) -> io::Result<()> {
+        issues.into_iter().try_for_each(|issue| self.push(issue))
+    }
+
+    /// Number of issues found, kept or not.
+    pub fn len(&self) -> usize {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Number of issues that were counted, but not kept.
+    pub fn dropped(&self) -> usize {
+        self.total - self.issues.len() - self.spill.as_ref().map_or(0, |spill| spill.count)
+    }
+
+    /// Number of issues by validator and severity, kept or not.
+    pub fn counts(&self) -> impl Iterator<Item = (&str, Severity, usize)> {
+        self.counts
+            .iter()
+            .map(|((validator, severity), count)| (validator.as_str(), *severity, *count))
+    }
+
+    /// The most severe issue found, if any.
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.counts.keys().map(|(_, severity)| *severity).max()
+    }
+
+    /// The first issues found, the ones kept in memory.
+    pub fn in_memory(&self) -> &[ValidationIssue] {
+        &self.issues
+    }
+
+    /// All issues kept, in the order they were found: the ones in memory, then the spilled ones.
+    pub fn issues(&mut self) -> io::Result<impl Iterator<Item = io::Result<ValidationIssue>> + '_> {
+        let spilled = match &mut self.spill {
+            Some(spill) => {
+                spill.writer.flush()?;
+                Some(BufReader::new(File::open(spill.file.path())?))
+            }
+            None => None,
+        };
+        let spilled = spilled
+            .into_iter()
+            .flat_map(|reader| reader.lines())
+            .map(|line| Ok(serde_json::from_str(&line?)?));
+        Ok(self.issues.iter().cloned().map(Ok).chain(spilled))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issue(row: usize) -> ValidationIssue {
+        ValidationIssue {
+            severity: if row.is_multiple_of(2) { Severity::Warning } else { Severity::Error },
+            original: Some("é".to_string()),
+            ..ValidationIssue::new("illegal_chars", row, format!("row {}", row))
+        }
+    }
+
+    #[test]
+    fn test_spill() {
+        let mut log = IssueLog::new(IssueStorage { mode: IssueMode::Spill, max_in_memory: 2 });
+        log.extend((1..=5).map(issue)).unwrap();
+        assert_eq!((log.len(), log.in_memory().len(), log.dropped()), (5, 2, 0));
+
+        let issues: Vec<ValidationIssue> = log.issues().unwrap().map(Result::unwrap).collect();
+        assert_eq!(issues, (1..=5).map(issue).collect::<Vec<_>>());
+
+        let path = log.spill.as_ref().unwrap().file.path().to_path_buf();
+        assert!(path.exists());
+        drop(log);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut log = IssueLog::new(IssueStorage { mode: IssueMode::Summary, max_in_memory: 2 });
+        log.extend((1..=5).map(issue)).unwrap();
+
+        assert_eq!(log.issues().unwrap().count(), 2);
+        assert_eq!(log.dropped(), 3);
+        assert_eq!(
+            log.counts().collect::<Vec<_>>(),
+            [("illegal_chars", Severity::Warning, 2), ("illegal_chars", Severity::Error, 3)]
+        );
+        assert_eq!(log.max_severity(), Some(Severity::Error));
+        assert!(log.spill.is_none());
+    }
+}
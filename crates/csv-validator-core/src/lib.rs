@@ -1,15 +1,20 @@
-use rayon::prelude::*;
+pub mod config;
+pub mod dataset;
+pub mod engine;
+pub mod issue_log;
 pub mod readers;
 
 pub mod utils;
 pub mod validators;
-use crate::readers::csv_readers::{CsvBatchIterator, RawBatchIterator};
-use crate::utils::csv_utils::{
-    infer_multi_char_separator, infer_separator, infer_separator_from_file,
-};
-use crate::validators::line_validators::{validate_line_field_count, validate_line_separator};
-use validators::line_validators::{Validator, Validators};
-use crate::validators::issue::ValidationResult;
+use crate::config::CommonConfig;
+use crate::engine::{Limits, Sinks, Summary, process_input, thread_pool};
+use crate::issue_log::IssueStorage;
+use crate::utils::csv_utils::{DEFAULT_SAMPLE_RECORDS, infer_separator, infer_separator_from_file};
+use crate::validators::Validator;
+use crate::validators::issue::Policy;
+use crate::validators::line_validators::{FieldCount, FieldCountConfig, Separator, SeparatorConfig};
+use std::fs::File;
+use std::io::{self, BufReader};
 
 pub fn check_csv(csv_filename: &str) -> Result<char, Box<dyn std::error::Error>> {
     let csv = std::fs::read_to_string(csv_filename)?;
@@ -19,86 +24,127 @@ pub fn check_csv(csv_filename: &str) -> Result<char, Box<dyn std::error::Error>>
     Ok(separator)
 }
 
-// fn check_buffered_lines<'a>(lines: &'a [&'a str], funcs: &'a Validators) {
-//     lines.par_iter().for_each(|&line| {
-//         let result = funcs.iter().try_fold(line, |current, f| {
-//             // If current is Some, call the function; otherwise keep None.
-//             f(current)
-//         });
-//
-//         match result {
-//             Some(final_line) => println!("Processed: {}", final_line),
-//             None => println!("Processing stopped for line: '{}'", line),
-//         }
-//     });
-// }
-
-fn check_buffered_lines(lines: &[&str], validators: & Validators) {
-    lines.par_iter().enumerate().for_each(|(i, &line)| {
-        // Start with the original line wrapped in our structure
-        let initial = ValidationResult::new(line.to_string());
-        // Chain the validators – each gets the current result and the line number (i+1)
-        let final_result = validators.iter().fold(initial, |acc, validator| {
-            validator(acc, i + 1)
-        });
-
-        println!("Final processed line {}: {}", i + 1, final_result.line);
-        if !final_result.issues.is_empty() {
-            println!("Issues found:");
-            for issue in final_result.issues {
-                println!(
-                    "  At line {} (pos: {:?}): {} [{}]",
-                    issue.line_number,
-                    issue.position,
-                    issue.message,
-                    if issue.fixed { "fixed" } else { "not fixed" }
-                );
-            }
-        } else {
-            println!("No issues found on this line.");
-        }
-    });
-}
-
+/// Validate a file without writing the rows anywhere; returns the issues found.
 pub fn validate_file(
     csv_filename: &str,
-    validators: Validators,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let iterator = RawBatchIterator::new(csv_filename, 5)?;
-
-    for batch in iterator {
-        println!("Batch of {} records:", batch.len());
-        let batch_refs: Vec<&str> = batch.iter().map(|s| s.as_str()).collect();
-        check_buffered_lines(&batch_refs, &validators);
-    }
+    validators: &mut [Box<dyn Validator>],
+    common: &CommonConfig,
+) -> Result<Summary, Box<dyn std::error::Error>> {
+    let reader = BufReader::new(File::open(csv_filename)?);
+    let mut sinks = Sinks {
+        output: Box::new(io::sink()),
+        rejects: None,
+        reject_reason: false,
+        issues: IssueStorage::default(),
+    };
+    let summary = process_input(
+        reader,
+        validators,
+        &mut sinks,
+        common,
+        &Limits::default(),
+        100 * 1024 * 1024,
+        &thread_pool(rayon::current_num_threads())?,
+    )?;
 
-    Ok(())
+    Ok(summary)
 }
 
+/// Check the field count and separator of a file, with the separator detected from its first records;
+/// returns the issues found, for the caller to report.
 pub fn main_validate(
     csv_filename: &str,
     num_fields: usize,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let separator = infer_separator_from_file(csv_filename)?;
-    dbg!(&separator);
-    let sep = separator.clone();
-    let funcs: Vec<Box<Validator>> = vec![
-        // Box::new(move |input| validate_line_field_count(input, num_fields, &sep.clone(), Some('"'), 0)),
-        Box::new(move |result: ValidationResult, line_number: usize| {
-            validate_line_field_count(result, num_fields, &sep.clone(),  Some('"'), line_number)
-        }),
-        // Box::new(move |input| validate_line_separator(input, ';') ),
-        Box::new(move |result: ValidationResult, line_number: usize| {
-            validate_line_separator(result, ';', line_number)
-        }),
+) -> Result<Summary, Box<dyn std::error::Error>> {
+    let separator = infer_separator_from_file(csv_filename, DEFAULT_SAMPLE_RECORDS)?;
+    let common = CommonConfig {
+        separator: Some(separator),
+        ..CommonConfig::default()
+    };
+    let mut validators: Vec<Box<dyn Validator>> = vec![
+        Box::new(FieldCount::new(FieldCountConfig {
+            expected: num_fields,
+            common: common.clone(),
+            policy: Policy::default(),
+        })),
+        Box::new(Separator::new(SeparatorConfig {
+            common: common.clone(),
+            policy: Policy::default(),
+        })),
     ];
 
-    validate_file(csv_filename, &funcs)
+    validate_file(csv_filename, &mut validators, &common)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::ValidatorConfig;
+    use crate::utils::csv_utils::{LineTerminator, sniff_dialect};
+
+    #[test]
+    fn test_validate_with_detected_dialect() {
+        // single quotes around a field with the separator in it, and a header the types don't apply to
+        let data = "'id'|'name'\n1|'a|b'\n2|'c'\n";
+        let mut config: ValidatorConfig = serde_yaml::from_str(
+            "common: {}
+schema:
+  columns: [{name: id, type: integer}]
+validators:
+  - {type: field_count, expected: 2, enabled: true, common: {}}
+  - {type: column_types, enabled: true, common: {}}",
+        )
+        .unwrap();
+        assert!(config.common.needs_dialect());
+        // validators built before the dialect is known, and bound to it, are the same as built after
+        let prototypes = config.clone().build_validators().unwrap();
+        config.common.apply_dialect(&sniff_dialect(data));
+        let common = config.common.clone();
+        let mut validators = config.build_validators().unwrap();
+        let pool = thread_pool(1).unwrap();
+
+        let mut sinks = Sinks {
+            output: Box::new(io::sink()),
+            rejects: None,
+            reject_reason: false,
+            issues: IssueStorage::default(),
+        };
+        let limits = Limits::default();
+        let summary =
+            process_input(data.as_bytes(), &mut validators, &mut sinks, &common, &limits, 1024, &pool).unwrap();
+        assert!(summary.issues.is_empty(), "{:?}", summary.issues.in_memory());
+        assert_eq!(summary.stats.rows, 3);
+
+        let mut bound = prototypes.clone();
+        for v in &mut bound {
+            v.bind_dialect(&common);
+        }
+        let summary = process_input(data.as_bytes(), &mut bound, &mut sinks, &common, &limits, 1024, &pool).unwrap();
+        assert!(summary.issues.is_empty(), "{:?}", summary.issues.in_memory());
+        // the prototypes are left unbound, for the next input
+        let summary =
+            process_input(data.as_bytes(), &mut prototypes.clone(), &mut sinks, &common, &limits, 1024, &pool).unwrap();
+        assert!(!summary.issues.is_empty());
+    }
+
+    #[test]
+    fn test_validate_file_with_cr_lines() {
+        // lines end at CR alone, also inside a quoted field: one record per row, not one for the whole file
+        let csv_filename = "../../examples/cr_with_header.csv";
+        let mut common = CommonConfig::default();
+        common.apply_dialect(&sniff_dialect(&std::fs::read_to_string(csv_filename).unwrap()));
+        assert_eq!(common.line_terminator, Some(LineTerminator::Cr));
+        let mut validators: Vec<Box<dyn Validator>> = vec![Box::new(FieldCount::new(FieldCountConfig {
+            expected: 3,
+            common: common.clone(),
+            policy: Policy::default(),
+        }))];
+
+        let summary = validate_file(csv_filename, &mut validators, &common).unwrap();
+        assert_eq!(summary.stats.rows, 5);
+        let issues = summary.issues.in_memory();
+        assert_eq!(issues.iter().map(|i| (i.line_number, i.start_line)).collect::<Vec<_>>(), [(4, Some(5))]);
+    }
 
     #[test]
     fn test_check_csv() {
@@ -114,7 +160,30 @@ mod tests {
     #[test]
     fn test_main_check_validate() {
         let csv_filename = "../../examples/full_quoted_with_header_semicolon.csv";
-        let result = main_validate(csv_filename, 7);
-        assert!(result.is_err());
+        // the separators inside the quoted colors no longer hide the separator;
+        // the wrong field count is reported, not an error
+        let summary = main_validate(csv_filename, 7).unwrap();
+        assert!(!summary.issues.is_empty());
+        assert!(summary.issues.in_memory().iter().all(|issue| issue.validator == "field_count"));
+
+        assert!(main_validate("../../examples/does_not_exist.csv", 7).is_err());
+    }
+
+    #[test]
+    fn test_validate_file() {
+        let common = CommonConfig {
+            separator: Some(";".to_string()),
+            has_header: Some(true),
+            ..CommonConfig::default()
+        };
+        let mut validators: Vec<Box<dyn Validator>> = vec![Box::new(FieldCount::new(FieldCountConfig {
+            expected: 5,
+            common: common.clone(),
+            policy: Policy::default(),
+        }))];
+
+        let summary = validate_file("../../examples/typed_with_header.csv", &mut validators, &common).unwrap();
+        assert!(summary.issues.is_empty());
+        assert!(summary.stats.rows > 1);
     }
 }
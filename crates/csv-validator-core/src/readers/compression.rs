@@ -0,0 +1,239 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Compression of the input or the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// Detect the compression from the magic bytes the data starts with.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use csv_validator_core::readers::compression::Compression;
+    ///
+    /// assert_eq!(Compression::from_magic(b"\x1F\x8B\x08\x00"), Compression::Gzip);
+    /// assert_eq!(Compression::from_magic(b"\x28\xB5\x2F\xFD"), Compression::Zstd);
+    /// assert_eq!(Compression::from_magic(b"id;name\n"), Compression::None);
+    /// ```
+    pub fn from_magic(data: &[u8]) -> Compression {
+        match data {
+            [0x1F, 0x8B, ..] => Compression::Gzip,
+            [0x28, 0xB5, 0x2F, 0xFD, ..] => Compression::Zstd,
+            [b'B', b'Z', b'h', ..] => Compression::Bzip2,
+            [0xFD, b'7', b'z', b'X', b'Z', 0x00, ..] => Compression::Xz,
+            _ => Compression::None,
+        }
+    }
+
+    /// The compression a file name suggests: `.gz`, `.zst`, `.bz2` or `.xz`.
+    pub fn from_path(path: impl AsRef<Path>) -> Compression {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            Some("bz2") => Compression::Bzip2,
+            Some("xz") => Compression::Xz,
+            _ => Compression::None,
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Bzip2 => "bzip2",
+            Compression::Xz => "xz",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            "bzip2" | "bz2" => Ok(Compression::Bzip2),
+            "xz" => Ok(Compression::Xz),
+            _ => Err(format!("Unknown compression: '{}'", s)),
+        }
+    }
+}
+
+/// Wrap `reader` so it yields the decompressed input, whatever the compression its magic bytes show.
+/// Input that isn't compressed is passed through as is. Returns the compression of the input.
+///
+/// # Example
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use csv_validator_core::readers::compression::{compress_writer, decompress_reader, Compression};
+///
+/// let mut writer = compress_writer(Vec::new(), Compression::Gzip).unwrap();
+/// writer.write_all(b"id;name\n1;a\n").unwrap();
+/// let compressed = writer.finish().unwrap();
+///
+/// let (compression, mut reader) = decompress_reader(compressed.as_slice()).unwrap();
+/// let mut text = String::new();
+/// reader.read_to_string(&mut text).unwrap();
+/// assert_eq!(compression, Compression::Gzip);
+/// assert_eq!(text, "id;name\n1;a\n");
+/// ```
+pub fn decompress_reader<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<(Compression, Box<dyn BufRead + 'a>)> {
+    let compression = Compression::from_magic(reader.fill_buf()?);
+    // the decoders of concatenated files (or streams) read them all
+    let reader: Box<dyn BufRead + 'a> = match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+        Compression::Bzip2 => Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader))),
+        Compression::Xz => Box::new(BufReader::new(liblzma::bufread::XzDecoder::new_multi_decoder(reader))),
+    };
+    Ok((compression, reader))
+}
+
+/// Wrap `writer` so what is written to it is compressed, at the default level of the format.
+/// The compressed stream is only complete once [`CompressWriter::finish`] is called.
+pub fn compress_writer<W: Write>(writer: W, compression: Compression) -> io::Result<CompressWriter<W>> {
+    Ok(CompressWriter(match compression {
+        Compression::None => Encoder::None(writer),
+        Compression::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(writer, flate2::Compression::default())),
+        Compression::Zstd => Encoder::Zstd(zstd::stream::write::Encoder::new(writer, 0)?),
+        Compression::Bzip2 => Encoder::Bzip2(bzip2::write::BzEncoder::new(writer, bzip2::Compression::default())),
+        Compression::Xz => Encoder::Xz(liblzma::write::XzEncoder::new(writer, 6)),
+    }))
+}
+
+/// A writer that compresses what is written to it, made by [`compress_writer`].
+pub struct CompressWriter<W: Write>(Encoder<W>);
+
+enum Encoder<W: Write> {
+    None(W),
+    Gzip(flate2::write::GzEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    Bzip2(bzip2::write::BzEncoder<W>),
+    Xz(liblzma::write::XzEncoder<W>),
+}
+
+impl<W: Write> CompressWriter<W> {
+    /// Write the end of the compressed stream and flush the writer underneath, which is returned.
+    /// A writer that is dropped instead may leave the stream truncated, without an error to show for it.
+    pub fn finish(self) -> io::Result<W> {
+        let mut writer = match self.0 {
+            Encoder::None(writer) => writer,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+            Encoder::Bzip2(encoder) => encoder.finish()?,
+            Encoder::Xz(encoder) => encoder.finish()?,
+        };
+        writer.flush()?;
+        Ok(writer)
+    }
+
+    fn inner(&mut self) -> &mut dyn Write {
+        match &mut self.0 {
+            Encoder::None(writer) => writer,
+            Encoder::Gzip(encoder) => encoder,
+            Encoder::Zstd(encoder) => encoder,
+            Encoder::Bzip2(encoder) => encoder,
+            Encoder::Xz(encoder) => encoder,
+        }
+    }
+}
+
+impl<W: Write> Write for CompressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner().write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner().write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_round_trip() {
+        let text = "id;name\n1;Ren\u{e9}e\n".repeat(100);
+        for compression in [
+            Compression::None,
+            Compression::Gzip,
+            Compression::Zstd,
+            Compression::Bzip2,
+            Compression::Xz,
+        ] {
+            let mut writer = compress_writer(Vec::new(), compression).unwrap();
+            writer.write_all(text.as_bytes()).unwrap();
+            let compressed = writer.finish().unwrap();
+            // concatenated streams are read as one
+            let twice = [compressed.as_slice(), compressed.as_slice()].concat();
+
+            let (detected, mut reader) = decompress_reader(twice.as_slice()).unwrap();
+            let mut decompressed = String::new();
+            reader.read_to_string(&mut decompressed).unwrap();
+            assert_eq!(detected, compression);
+            assert_eq!(decompressed, text.repeat(2), "{}", compression);
+        }
+    }
+
+    /// A destination with room for only a few bytes.
+    struct Full(usize);
+
+    impl Write for Full {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.len() > self.0 {
+                return Err(io::Error::new(io::ErrorKind::StorageFull, "no space left"));
+            }
+            self.0 -= buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_finish_errors() {
+        // the encoders buffer what is written, so the error only shows when the stream is finished
+        for compression in [Compression::Gzip, Compression::Zstd, Compression::Bzip2, Compression::Xz] {
+            let mut writer = compress_writer(Full(16), compression).unwrap();
+            writer.write_all(b"id;name\n").unwrap();
+            let error = writer.finish().err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::StorageFull, "{}", compression);
+        }
+    }
+
+    #[test]
+    fn test_compression_names() {
+        assert_eq!(Compression::from_path("feed.csv.zst"), Compression::Zstd);
+        assert_eq!(Compression::from_path("feed.csv"), Compression::None);
+        assert_eq!("bz2".parse::<Compression>(), Ok(Compression::Bzip2));
+        assert_eq!(Compression::Xz.to_string(), "xz");
+        assert!("rar".parse::<Compression>().is_err());
+    }
+}
@@ -1,47 +1,176 @@
 use std::fs::File;
-use std::io::{BufRead, BufReader, Lines};
+use std::io::{BufRead, BufReader};
 
-use crate::utils::csv_utils::line_processor;
+use crate::utils::csv_utils::{LineTerminator, line_processor};
+use crate::utils::tokenizer::RecordEnds;
 use std::io;
 
-pub struct RawBatchIterator {
-    lines: Lines<BufReader<File>>,
+/// A logical CSV record.
+/// A quoted field may contain line breaks, so one record can span several physical lines;
+/// `start_line` and `end_line` are the (1-based) physical line numbers it was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub text: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    /// The bytes of the record when they aren't valid UTF-8; `text` has U+FFFD in place of the invalid sequences.
+    pub raw: Option<Vec<u8>>,
+}
+
+/// A logical CSV record, split into fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvRecord {
+    pub fields: Vec<String>,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// Reads logical records instead of physical lines.
+/// Quote state is tracked across lines: a line break inside a quoted field does not end the record.
+/// A doubled quote inside a quoted field is an escaped quote and keeps the field open, and a quote in the
+/// middle of an unquoted field is just a character (see [`RecordEnds`]); `separator` is `None` while it's
+/// being detected.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::readers::csv_readers::RecordReader;
+///
+/// let data = "id,address\n1,\"Main Street 1\nBrussels\"\n2,\"Station \"\"Road\"\"\"\n";
+/// let records: Vec<_> = RecordReader::new(data.as_bytes(), Some(","), Some('"'))
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+///
+/// assert_eq!(records.len(), 3);
+/// assert_eq!(records[1].text, "1,\"Main Street 1\nBrussels\"");
+/// assert_eq!((records[1].start_line, records[1].end_line), (2, 3));
+/// assert_eq!((records[2].start_line, records[2].end_line), (4, 4));
+/// ```
+pub struct RecordReader<R: BufRead> {
+    reader: R,
+    ends: RecordEnds,
+    line_number: usize,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(reader: R, separator: Option<&str>, quote_char: Option<char>) -> Self {
+        Self {
+            reader,
+            ends: RecordEnds::new(separator, quote_char),
+            line_number: 0,
+        }
+    }
+
+    /// Read lines that end at CR alone with `LineTerminator::Cr`; by default lines end at LF or CRLF.
+    pub fn line_terminator(mut self, line_terminator: LineTerminator) -> Self {
+        self.ends = self.ends.line_terminator(line_terminator);
+        self
+    }
+}
+
+fn strip_line_ending(text: &mut Vec<u8>, terminator: u8) {
+    if text.last() == Some(&terminator) {
+        text.pop();
+        if terminator == b'\n' && text.ends_with(b"\r") {
+            text.pop();
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // bytes rather than a String: invalid UTF-8 is left for the validators to report
+        let mut bytes = Vec::new();
+        let start_line = self.line_number + 1;
+
+        loop {
+            let start = bytes.len();
+            match self.reader.read_until(self.ends.terminator(), &mut bytes) {
+                // end of input; an unterminated quoted field ends the record as well
+                Ok(0) if bytes.is_empty() => return None,
+                Ok(0) => break,
+                Ok(_) => {
+                    self.line_number += 1;
+                    if self.ends.find(&bytes[start..]).is_some() {
+                        break;
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        strip_line_ending(&mut bytes, self.ends.terminator());
+        let (text, raw) = match String::from_utf8(bytes) {
+            Ok(text) => (text, None),
+            Err(e) => (String::from_utf8_lossy(e.as_bytes()).into_owned(), Some(e.into_bytes())),
+        };
+        Some(Ok(Record {
+            text,
+            start_line,
+            end_line: self.line_number,
+            raw,
+        }))
+    }
+}
+
+/// Batches of records. A read error is returned after the records read before it.
+pub struct RawBatchIterator<R: BufRead = BufReader<File>> {
+    records: RecordReader<R>,
     batch_size: usize,
+    error: Option<io::Error>,
 }
 
 impl RawBatchIterator {
-    pub fn new(filename: &str, batch_size: usize) -> io::Result<Self> {
+    pub fn new(filename: &str, batch_size: usize, quote_char: Option<char>) -> io::Result<Self> {
         let file = File::open(filename)?;
-        let rdr = BufReader::new(file);
-        let lines = rdr.lines();
-        Ok(Self { lines, batch_size })
+        Ok(Self::from_reader(BufReader::new(file), batch_size, quote_char))
     }
 }
 
-impl Iterator for RawBatchIterator {
-    type Item = Vec<String>;
+impl<R: BufRead> RawBatchIterator<R> {
+    pub fn from_reader(reader: R, batch_size: usize, quote_char: Option<char>) -> Self {
+        let records = RecordReader::new(reader, None, quote_char);
+        Self { records, batch_size, error: None }
+    }
+}
+
+impl<R: BufRead> Iterator for RawBatchIterator<R> {
+    type Item = io::Result<Vec<Record>>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
         let mut batch = Vec::with_capacity(self.batch_size);
 
         for _ in 0..self.batch_size {
-            match self.lines.next() {
-                Some(Ok(line)) => batch.push(line),
-                Some(Err(_)) => continue, // skip errors
+            match self.records.next() {
+                Some(Ok(record)) => batch.push(record),
+                Some(Err(e)) if batch.is_empty() => return Some(Err(e)),
+                Some(Err(e)) => {
+                    self.error = Some(e);
+                    break;
+                }
                 None => break,
             }
         }
 
-        if batch.is_empty() { None } else { Some(batch) }
+        if batch.is_empty() { None } else { Some(Ok(batch)) }
     }
 }
 
-pub struct CsvBatchIterator {
-    lines: Lines<BufReader<File>>,
+/// Batches of records split into fields. A read error, or a record that can't be split,
+/// is returned after the records read before it.
+pub struct CsvBatchIterator<R: BufRead = BufReader<File>> {
+    records: RecordReader<R>,
+    error: Option<io::Error>,
     batch_size: usize,
     separator: String,
     quote_char: Option<char>,
     has_header: bool,
+    header: Option<Vec<String>>,
 }
 
 impl CsvBatchIterator {
@@ -53,37 +182,157 @@ impl CsvBatchIterator {
         has_header: bool,
     ) -> io::Result<Self> {
         let file = File::open(filename)?;
-        let rdr = BufReader::new(file);
-        let lines = rdr.lines();
-        Ok(Self {
-            lines,
+        Ok(Self::from_reader(
+            BufReader::new(file),
+            batch_size,
+            separator,
+            quote_char,
+            has_header,
+        ))
+    }
+}
+
+impl<R: BufRead> CsvBatchIterator<R> {
+    pub fn from_reader(
+        reader: R,
+        batch_size: usize,
+        separator: String,
+        quote_char: Option<char>,
+        has_header: bool,
+    ) -> Self {
+        Self {
+            records: RecordReader::new(reader, Some(&separator), quote_char),
+            error: None,
             batch_size,
             separator,
             quote_char,
             has_header,
+            header: None,
+        }
+    }
+
+    /// The header fields, available once the first batch has been read (if `has_header` is set).
+    pub fn header(&self) -> Option<&[String]> {
+        self.header.as_deref()
+    }
+
+    fn parse(&self, record: Record) -> io::Result<CsvRecord> {
+        let fields = line_processor(&record.text, &self.separator, self.quote_char).map_err(|e| {
+            let message = format!("line {}: {}", record.start_line, e);
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })?;
+        Ok(CsvRecord {
+            fields,
+            start_line: record.start_line,
+            end_line: record.end_line,
         })
     }
 }
 
-impl Iterator for CsvBatchIterator {
-    type Item = Vec<Vec<String>>;
+impl<R: BufRead> Iterator for CsvBatchIterator<R> {
+    type Item = io::Result<Vec<CsvRecord>>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        if self.has_header && self.header.is_none() {
+            match self.records.next().map(|record| record.and_then(|record| self.parse(record))) {
+                Some(Ok(record)) => self.header = Some(record.fields),
+                Some(Err(e)) => return Some(Err(e)),
+                None => return None,
+            }
+        }
+
         let mut batch = Vec::with_capacity(self.batch_size);
 
         for _ in 0..self.batch_size {
-            match self.lines.next() {
-                Some(Ok(line)) => {
-                    let split_line =
-                        line_processor(&line, &self.separator.clone(), self.quote_char).ok()?;
-                    batch.push(split_line)
+            match self.records.next().map(|record| record.and_then(|record| self.parse(record))) {
+                Some(Ok(record)) => batch.push(record),
+                Some(Err(e)) if batch.is_empty() => return Some(Err(e)),
+                Some(Err(e)) => {
+                    self.error = Some(e);
+                    break;
                 }
-                Some(Err(_)) => continue, // skip errors
                 None => break,
             }
         }
 
-        if batch.is_empty() { None } else { Some(batch) }
+        if batch.is_empty() { None } else { Some(Ok(batch)) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_reader_multi_line() {
+        let data = "a,b\r\n\"1\r\n2\",x\r\n\"say \"\"hi\"\"\nthere\",y\n3,z";
+        let records: Vec<Record> = RecordReader::new(data.as_bytes(), Some(","), Some('"'))
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].text, "a,b");
+        assert_eq!(records[1].text, "\"1\r\n2\",x");
+        assert_eq!((records[1].start_line, records[1].end_line), (2, 3));
+        assert_eq!(records[2].text, "\"say \"\"hi\"\"\nthere\",y");
+        assert_eq!((records[2].start_line, records[2].end_line), (4, 5));
+        assert_eq!((records[3].start_line, records[3].end_line), (6, 6));
+    }
+
+    #[test]
+    fn test_record_reader_without_quote_char() {
+        let data = "\"a\nb\"";
+        let records: Vec<Record> = RecordReader::new(data.as_bytes(), Some(","), None)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(records.len(), 2);
+    }
+
+    #[test]
+    fn test_record_reader_stray_quote() {
+        // a quote in the middle of an unquoted field doesn't open a quoted field
+        let data = "id,name\n1,5\" screen\n2,b\n3,\"c\nd\"\n";
+        let records: Vec<Record> = RecordReader::new(data.as_bytes(), Some(","), Some('"'))
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let texts: Vec<&str> = records.iter().map(|record| record.text.as_str()).collect();
+        assert_eq!(texts, ["id,name", "1,5\" screen", "2,b", "3,\"c\nd\""]);
+        assert_eq!((records[3].start_line, records[3].end_line), (4, 5));
+    }
+
+    #[test]
+    fn test_record_reader_cr_lines() {
+        // lines that end at CR alone, with a CR inside a quoted field, and LF taken as any other character
+        let data = "id,name\r1,\"a\rb\"\r2,c\nd\r";
+        let records: Vec<Record> = RecordReader::new(data.as_bytes(), Some(","), Some('"'))
+            .line_terminator(LineTerminator::Cr)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let texts: Vec<&str> = records.iter().map(|record| record.text.as_str()).collect();
+        assert_eq!(texts, ["id,name", "1,\"a\rb\"", "2,c\nd"]);
+        assert_eq!((records[1].start_line, records[1].end_line), (2, 3));
+        assert_eq!(records[2].start_line, 4);
+    }
+
+    #[test]
+    fn test_csv_batch_iterator() {
+        let data = "id;address\n1;\"Main Street 1\nBrussels\"\n2;\"Station Road\"\n";
+        let mut iterator =
+            CsvBatchIterator::from_reader(data.as_bytes(), 5, ";".to_string(), Some('"'), true);
+
+        let batch = iterator.next().unwrap().unwrap();
+        assert_eq!(iterator.header().unwrap(), ["id", "address"]);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[0].fields, ["1", "Main Street 1\nBrussels"]);
+        assert_eq!((batch[0].start_line, batch[0].end_line), (2, 3));
+        assert_eq!(batch[1].start_line, 4);
+        assert!(iterator.next().is_none());
     }
 }
 
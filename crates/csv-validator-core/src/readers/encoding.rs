@@ -0,0 +1,311 @@
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use std::str::FromStr;
+
+use encoding_rs::{Decoder, Encoder, EncoderResult, UTF_16BE, UTF_16LE};
+pub use encoding_rs::{Encoding, UTF_8};
+
+use crate::utils::profile::detect_encoding;
+
+/// Bytes read from the input to detect its encoding.
+pub const ENCODING_SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Look up an encoding by name: utf-8, windows-1252, latin-1, utf-16le, utf-16be, or any other
+/// [WHATWG label](https://encoding.spec.whatwg.org/#names-and-labels).
+/// Like in browsers, latin-1 (iso-8859-1) is read as its superset windows-1252.
+pub fn encoding_for_label(label: &str) -> Result<&'static Encoding, String> {
+    let label = label.trim().to_lowercase();
+    let label = match label.as_str() {
+        "latin-1" => "latin1",
+        label => label,
+    };
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| format!("Unknown encoding: '{}'", label))
+}
+
+/// The encoding of the input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEncoding {
+    /// Detect the encoding from the byte order mark, or else from a sample of the input.
+    Auto,
+    Known(&'static Encoding),
+}
+
+impl FromStr for InputEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(InputEncoding::Auto),
+            label => encoding_for_label(label).map(InputEncoding::Known),
+        }
+    }
+}
+
+/// The encoding of the output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputEncoding {
+    /// The encoding the input was read in.
+    Input,
+    Known(&'static Encoding),
+}
+
+impl OutputEncoding {
+    pub fn resolve(self, input: &'static Encoding) -> &'static Encoding {
+        match self {
+            OutputEncoding::Input => input,
+            OutputEncoding::Known(encoding) => encoding,
+        }
+    }
+}
+
+impl FromStr for OutputEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "input" => Ok(OutputEncoding::Input),
+            label => encoding_for_label(label).map(OutputEncoding::Known),
+        }
+    }
+}
+
+/// The encoding of an input that starts with `sample`, and the length of its byte order mark.
+/// A byte order mark wins over the given encoding; `Auto` needs a sample of [`ENCODING_SAMPLE_SIZE`] bytes.
+pub fn resolve_encoding(sample: &[u8], encoding: InputEncoding) -> (&'static Encoding, usize) {
+    match (Encoding::for_bom(sample), encoding) {
+        (Some((encoding, bom_length)), _) => (encoding, bom_length),
+        (None, InputEncoding::Known(encoding)) => (encoding, 0),
+        (None, InputEncoding::Auto) => (encoding_for_label(detect_encoding(sample)).unwrap_or(UTF_8), 0),
+    }
+}
+
+/// Wrap `reader` so it yields UTF-8, whatever the encoding of the input.
+/// A byte order mark wins over the given encoding, and is left out.
+/// Returns the encoding the input is read in.
+///
+/// UTF-8 input is passed through as is, so invalid bytes are left for the reader to report.
+///
+/// # Example
+///
+/// ```
+/// use std::io::Read;
+/// use csv_validator_core::readers::encoding::{decode_reader, InputEncoding};
+///
+/// let input: &[u8] = b"id;name\n1;Ren\xE9e\n";
+/// let (encoding, mut reader) = decode_reader(input, InputEncoding::Auto).unwrap();
+///
+/// let mut text = String::new();
+/// reader.read_to_string(&mut text).unwrap();
+/// assert_eq!(encoding.name(), "windows-1252");
+/// assert_eq!(text, "id;name\n1;Ren\u{e9}e\n");
+/// ```
+pub fn decode_reader<R: Read + 'static>(
+    mut reader: R,
+    encoding: InputEncoding,
+) -> io::Result<(&'static Encoding, Box<dyn BufRead>)> {
+    let sample_size = match encoding {
+        InputEncoding::Auto => ENCODING_SAMPLE_SIZE,
+        // just enough for a byte order mark
+        InputEncoding::Known(_) => 3,
+    };
+    let mut sample = Vec::with_capacity(sample_size);
+    reader.by_ref().take(sample_size as u64).read_to_end(&mut sample)?;
+
+    let (encoding, bom_length) = resolve_encoding(&sample, encoding);
+    sample.drain(..bom_length);
+    let input = Cursor::new(sample).chain(reader);
+
+    let reader: Box<dyn BufRead> = if encoding == UTF_8 {
+        Box::new(BufReader::new(input))
+    } else {
+        Box::new(BufReader::new(DecodingReader::new(
+            input,
+            encoding.new_decoder_without_bom_handling(),
+        )))
+    };
+    Ok((encoding, reader))
+}
+
+/// Transcodes the input to UTF-8. Bytes that can't be decoded become U+FFFD.
+pub struct DecodingReader<R: Read> {
+    reader: R,
+    decoder: Decoder,
+    input: Vec<u8>,
+    decoded: String,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> DecodingReader<R> {
+    pub fn new(reader: R, decoder: Decoder) -> Self {
+        Self {
+            reader,
+            decoder,
+            input: vec![0; 64 * 1024],
+            decoded: String::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let read = self.reader.read(&mut self.input)?;
+        let last = read == 0;
+
+        self.decoded.clear();
+        self.position = 0;
+        // room for all of the input, so it's decoded in one go
+        let capacity = self.decoder.max_utf8_buffer_length(read).unwrap_or(read * 3 + 16);
+        self.decoded.reserve(capacity);
+        let _ = self.decoder.decode_to_string(&self.input[..read], &mut self.decoded, last);
+
+        self.finished = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.decoded.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let pending = &self.decoded.as_bytes()[self.position..];
+        let n = pending.len().min(buf.len());
+        buf[..n].copy_from_slice(&pending[..n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// Wrap `writer` so the UTF-8 written to it ends up in `encoding`.
+/// UTF-16 output starts with a byte order mark; characters the encoding can't represent become `?`.
+///
+/// # Example
+///
+/// ```
+/// use std::io::Write;
+/// use csv_validator_core::readers::encoding::{encode_writer, encoding_for_label};
+///
+/// let mut output = Vec::new();
+/// {
+///     let mut writer = encode_writer(&mut output, encoding_for_label("windows-1252").unwrap());
+///     writeln!(writer, "1;Ren\u{e9}e;\u{4e16}").unwrap();
+///     writer.flush().unwrap();
+/// }
+/// assert_eq!(output, b"1;Ren\xE9e;?\n");
+/// ```
+pub fn encode_writer<'a, W: Write + Send + 'a>(writer: W, encoding: &'static Encoding) -> Box<dyn Write + Send + 'a> {
+    if encoding == UTF_8 {
+        Box::new(writer)
+    } else {
+        Box::new(EncodingWriter {
+            writer,
+            encoding,
+            encoder: encoding.new_encoder(),
+            pending: Vec::new(),
+            started: false,
+        })
+    }
+}
+
+struct EncodingWriter<W: Write> {
+    writer: W,
+    encoding: &'static Encoding,
+    encoder: Encoder,
+    /// The start of a character that was split over two writes.
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl<W: Write> EncodingWriter<W> {
+    fn encode(&mut self, text: &str) -> io::Result<()> {
+        if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            // encoding_rs only decodes UTF-16
+            let little_endian = self.encoding == UTF_16LE;
+            let to_bytes = if little_endian { u16::to_le_bytes } else { u16::to_be_bytes };
+            let mut bytes = Vec::with_capacity(text.len() * 2 + 2);
+            if !self.started {
+                bytes.extend(to_bytes(0xFEFF));
+            }
+            bytes.extend(text.encode_utf16().flat_map(to_bytes));
+            self.started = true;
+            return self.writer.write_all(&bytes);
+        }
+
+        let mut bytes = Vec::with_capacity(text.len() + 16);
+        let mut text = text;
+        loop {
+            let (result, read) = self.encoder.encode_from_utf8_to_vec_without_replacement(text, &mut bytes, false);
+            text = &text[read..];
+            match result {
+                EncoderResult::InputEmpty => break,
+                EncoderResult::OutputFull => bytes.reserve(text.len() + 16),
+                EncoderResult::Unmappable(_) => bytes.push(b'?'),
+            }
+        }
+        self.started = true;
+        self.writer.write_all(&bytes)
+    }
+}
+
+impl<W: Write> Write for EncodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let pending = std::mem::take(&mut self.pending);
+        let valid = match std::str::from_utf8(&pending) {
+            Ok(text) => text.len(),
+            // keep a character that was cut off for the next write
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        // checked above
+        let text = std::str::from_utf8(&pending[..valid]).unwrap_or_default();
+        self.encode(text)?;
+        self.pending = pending[valid..].to_vec();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(input: &'static [u8], encoding: &str) -> (&'static str, String) {
+        let (encoding, mut reader) = decode_reader(input, encoding.parse().unwrap()).unwrap();
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        (encoding.name(), text)
+    }
+
+    #[test]
+    fn test_decode_reader() {
+        assert_eq!(read_all(b"a;b\n", "auto"), ("UTF-8", "a;b\n".to_string()));
+        assert_eq!(read_all(b"\xEF\xBB\xBFa;b\n", "utf-8"), ("UTF-8", "a;b\n".to_string()));
+        assert_eq!(read_all(b"caf\xE9\n", "latin-1"), ("windows-1252", "caf\u{e9}\n".to_string()));
+        // the byte order mark wins
+        assert_eq!(read_all(b"\xFF\xFEa\x00\n\x00", "windows-1252"), ("UTF-16LE", "a\n".to_string()));
+        assert_eq!(read_all(b"\x00a\x00\n", "utf-16be"), ("UTF-16BE", "a\n".to_string()));
+        assert!("ebcdic".parse::<InputEncoding>().is_err());
+    }
+
+    #[test]
+    fn test_encode_writer() {
+        let mut output = Vec::new();
+        {
+            let mut writer = encode_writer(&mut output, UTF_16LE);
+            // a character split over two writes
+            writer.write_all(&"é".as_bytes()[..1]).unwrap();
+            writer.write_all(&"é\n".as_bytes()[1..]).unwrap();
+        }
+        assert_eq!(output, b"\xFF\xFE\xE9\x00\n\x00");
+
+        assert_eq!(OutputEncoding::Input.resolve(UTF_16BE), UTF_16BE);
+        assert_eq!("utf-8".parse::<OutputEncoding>().unwrap().resolve(UTF_16BE), UTF_8);
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+
+pub use memmap2::Mmap;
+use memchr::memchr;
+use rayon::prelude::*;
+
+use crate::utils::tokenizer::RecordEnds;
+
+/// Map a file in memory, read-only.
+pub fn map_file(path: &str) -> io::Result<Mmap> {
+    let file = File::open(path)?;
+    // SAFETY: the map is only read. Like every reader, validation goes wrong if another process
+    // changes the file in the meantime, but a truncated file makes the process fail on access.
+    unsafe { Mmap::map(&file) }
+}
+
+/// A part of the input that starts and ends at a record boundary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub range: Range<usize>,
+    /// Index of the first record of the chunk in the whole input.
+    pub first_row: usize,
+    /// Number of physical lines before the chunk, to number the lines of its records in the whole input.
+    pub first_line: usize,
+}
+
+/// Split `data` into chunks of about `chunk_size` bytes, for the chunks to be read in parallel.
+/// Chunks end at a line break outside quoted fields, where `ends` finds the records end, like a
+/// `RecordReader` with the same separator, quote char and line terminator; every chunk knows the global
+/// row and line number it starts at. See [`split_chunks`].
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::readers::mmap::record_chunks;
+/// use csv_validator_core::utils::tokenizer::RecordEnds;
+///
+/// let data = b"id,note\n1,\"a\nb\"\n2,c\n3,d\n";
+/// let chunks = record_chunks(data, &RecordEnds::new(Some(","), Some('"')), 10);
+///
+/// // the line break inside the quoted note doesn't end a chunk
+/// assert_eq!(chunks.iter().map(|c| c.range.clone()).collect::<Vec<_>>(), [0..16, 16..24]);
+/// assert_eq!(chunks.iter().map(|c| c.first_row).collect::<Vec<_>>(), [0, 2]);
+/// ```
+pub fn record_chunks(data: &[u8], ends: &RecordEnds, chunk_size: usize) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    split_chunks(data, ends, chunk_size, |chunk| {
+        chunks.push(chunk);
+        true
+    });
+    chunks
+}
+
+/// Split `data` into chunks like [`record_chunks`], calling `each` with the chunks in order, as soon as
+/// they are found, until it returns false.
+///
+/// `data` is cut into pieces of about `chunk_size` bytes, at the first line break after every multiple of
+/// `chunk_size`. A piece starts either a record or a line of a quoted field, depending on the pieces before:
+/// the pieces are scanned in parallel, as many at a time as there are threads, from both states, and the
+/// scans that start from the state the piece before ends in are kept. A piece that starts inside a quoted
+/// field starts a chunk at the end of its first record instead, if it has one.
+pub fn split_chunks(data: &[u8], ends: &RecordEnds, chunk_size: usize, mut each: impl FnMut(Chunk) -> bool) {
+    let terminator = ends.terminator();
+    let window = rayon::current_num_threads();
+    // the chunk being split, its end still to be found
+    let mut chunk: Option<Chunk> = None;
+    let (mut rows, mut lines, mut in_quotes) = (0, 0, false);
+
+    let mut start = 0;
+    while start < data.len() {
+        let mut pieces = Vec::with_capacity(window);
+        while pieces.len() < window && start < data.len() {
+            let target = (start + chunk_size.max(1)).min(data.len());
+            let end = memchr(terminator, &data[target..]).map_or(data.len(), |i| target + i + 1);
+            pieces.push(start..end);
+            start = end;
+        }
+
+        let scans: Vec<[Scan; 2]> = pieces
+            .par_iter()
+            .map(|piece| [false, true].map(|in_quotes| Scan::new(&data[piece.clone()], ends, in_quotes)))
+            .collect();
+
+        for (piece, [outside, inside]) in pieces.into_iter().zip(scans) {
+            let scan = if in_quotes { inside } else { outside };
+            let boundary = match scan.first_end {
+                _ if !in_quotes => Some((piece.start, rows, lines)),
+                Some(end) => {
+                    let before = data[piece.start..piece.start + end].iter().filter(|&&b| b == terminator).count();
+                    Some((piece.start + end, rows + 1, lines + before))
+                }
+                // the quoted field runs through the whole piece
+                None => None,
+            };
+            if let Some((boundary, first_row, first_line)) = boundary
+                && chunk.as_ref().is_none_or(|chunk| chunk.range.start < boundary)
+            {
+                if let Some(mut done) = chunk.take() {
+                    done.range.end = boundary;
+                    if !each(done) {
+                        return;
+                    }
+                }
+                chunk = (boundary < data.len()).then_some(Chunk { range: boundary..data.len(), first_row, first_line });
+            }
+            rows += scan.records;
+            lines += scan.lines;
+            in_quotes = scan.in_quotes;
+        }
+    }
+    if let Some(chunk) = chunk {
+        each(chunk);
+    }
+}
+
+/// The records of a piece of the input, from a quote state at its start.
+struct Scan {
+    /// The records that end in the piece.
+    records: usize,
+    /// Just after the line break of the first record that ends in the piece, if one does.
+    first_end: Option<usize>,
+    /// The line breaks in the piece, quoted or not.
+    lines: usize,
+    /// Whether the piece ends inside a quoted field.
+    in_quotes: bool,
+}
+
+impl Scan {
+    fn new(piece: &[u8], ends: &RecordEnds, in_quotes: bool) -> Self {
+        let mut ends = ends.clone().in_quotes(in_quotes);
+        let (mut records, mut first_end, mut pos) = (0, None, 0);
+        while let Some(i) = ends.find(&piece[pos..]) {
+            pos += i + 1;
+            records += 1;
+            first_end.get_or_insert(pos);
+        }
+        let lines = piece.iter().filter(|&&b| b == ends.terminator()).count();
+        Self { records, first_end, lines, in_quotes: ends.is_in_quotes() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::readers::csv_readers::{Record, RecordReader};
+
+    fn ranges(data: &str, quote_char: Option<char>, chunk_size: usize) -> Vec<Range<usize>> {
+        record_chunks(data.as_bytes(), &RecordEnds::new(Some(","), quote_char), chunk_size)
+            .into_iter()
+            .map(|chunk| chunk.range)
+            .collect()
+    }
+
+    #[test]
+    fn test_record_chunks() {
+        assert!(ranges("", Some('"'), 4).is_empty());
+        assert_eq!(ranges("a\nb\nc", None, 1), [0..2, 2..4, 4..5]);
+        assert_eq!(ranges("a\nb\nc\n", None, 100), vec![0..6]);
+        // an escaped quote keeps the field open, a multi-byte quote is one quote
+        assert_eq!(ranges("\"a\"\"\nb\"\nc\n", Some('"'), 1), [0..8, 8..10]);
+        // a quoted field that runs through a whole piece
+        assert_eq!(ranges("\"a\nb\nc\"\nd\n", Some('"'), 1), [0..8, 8..10]);
+        assert_eq!(ranges("¤a\nb¤\nc\n", Some('¤'), 1), [0..8, 8..10]);
+        // a stray quote in an unquoted field doesn't keep the line breaks after it inside the record
+        assert_eq!(ranges("1,5\" x\n2,b\n3,c\n", Some('"'), 1), [0..7, 7..11, 11..15]);
+    }
+
+    #[test]
+    fn test_record_chunks_rows() {
+        // the chunks have as many records and lines as a RecordReader finds, with empty and unterminated
+        // records, and quotes in the middle of unquoted fields too
+        let data = "id,note\n\n1,\"x\ny\"\n2,5\" z\n4,w\n\"3\n";
+        let read = |record: io::Result<Record>, first_line: usize| {
+            let record = record.unwrap();
+            (record.text, record.start_line + first_line, record.end_line + first_line)
+        };
+        let all: Vec<_> = RecordReader::new(data.as_bytes(), Some(","), Some('"')).map(|r| read(r, 0)).collect();
+
+        for chunk_size in 1..data.len() {
+            let chunks = record_chunks(data.as_bytes(), &RecordEnds::new(Some(","), Some('"')), chunk_size);
+            let mut rows = Vec::new();
+            for chunk in &chunks {
+                assert_eq!(chunk.first_row, rows.len());
+                let records = RecordReader::new(&data.as_bytes()[chunk.range.clone()], Some(","), Some('"'));
+                rows.extend(records.map(|record| read(record, chunk.first_line)));
+            }
+            assert_eq!(rows, all);
+        }
+    }
+}
@@ -1 +1,4 @@
+pub mod compression;
 pub mod csv_readers;
+pub mod encoding;
+pub mod mmap;
@@ -1,6 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Chain, Cursor, Read};
+
+use serde::{Deserialize, Serialize};
+
+use crate::readers::csv_readers::RecordReader;
+use crate::utils::tokenizer::split_fields;
 
 use nom::{
     IResult, Parser,
@@ -114,14 +120,28 @@ pub fn line_processor<'a>(
         }
     }
 
-    // Output parsed fields.
-    for (i, field) in fields.iter().enumerate() {
-        println!("Field {}: {}", i + 1, field);
-    }
-    dbg!(&fields);
     Ok(fields)
 }
 
+/// Returns the byte offset at which every field of a line starts.
+/// Separators inside quoted fields are skipped; a doubled quote inside a quoted field is an escaped quote.
+/// A line always has at least one (possibly empty) field.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::utils::csv_utils::field_offsets;
+///
+/// let line = r#"1,"red,green",3"#;
+/// assert_eq!(field_offsets(line, ",", Some('"')), vec![0, 2, 14]);
+/// assert_eq!(field_offsets(line, ",", None).len(), 4);
+/// ```
+pub fn field_offsets(line: &str, separator: &str, quote_char: Option<char>) -> Vec<usize> {
+    let mut ranges = Vec::new();
+    split_fields(line, separator, quote_char, &mut ranges);
+    ranges.into_iter().map(|range| range.start).collect()
+}
+
 /// Use this function to infer the separator of a CSV file using statistical analysis,
 /// based on the number of occurrences of the most common separators.
 /// It will return the most likely separator.
@@ -150,15 +170,17 @@ pub fn infer_separator(csv: &str) -> char {
     separator
 }
 
-pub fn infer_separator_from_file(filename: &str) -> Result<String, Box<dyn std::error::Error>> {
-    // read first 5 lines of the file
+/// Infer the separator from the first `sample_records` lines of a file.
+pub fn infer_separator_from_file(
+    filename: &str,
+    sample_records: usize,
+) -> Result<String, Box<dyn std::error::Error>> {
     let file = File::open(filename)?;
     let reader = BufReader::new(file);
-    let lines: Vec<String> = reader.lines().take(5).collect::<Result<Vec<_>, _>>()?;
-    dbg!(&lines);
+    let lines: Vec<String> = reader.lines().take(sample_records).collect::<Result<Vec<_>, _>>()?;
 
     let sample = lines.join("\n");
-    let separator = infer_multi_char_separator(&sample);
+    let separator = infer_multi_char_separator_sampled(&sample, sample_records);
 
     match separator {
         Some(sep) => Ok(sep),
@@ -166,8 +188,11 @@ pub fn infer_separator_from_file(filename: &str) -> Result<String, Box<dyn std::
     }
 }
 
+/// Number of records [`infer_multi_char_separator`] looks at.
+pub const DEFAULT_SAMPLE_RECORDS: usize = 100;
+
 /// Use this function to infer the separator of a CSV file using statistical analysis.
-/// It will return the most likely separator.
+/// It will return the most likely separator, looking at the first [`DEFAULT_SAMPLE_RECORDS`] records.
 ///
 /// # Example
 ///
@@ -177,57 +202,432 @@ pub fn infer_separator_from_file(filename: &str) -> Result<String, Box<dyn std::
 /// let csv = "a,b,c\n1,2,3\n4,5,6";
 /// let separator = infer_multi_char_separator(csv);
 /// assert_eq!(separator, Some(",".into()));
+///
+/// // separators inside quotes don't count, and the odd short record doesn't spoil the rest
+/// let csv = "id;colors\n1;\"red;green;blue\"\n2\n3;\"cyan\"";
+/// assert_eq!(infer_multi_char_separator(csv), Some(";".into()));
 /// ```
-/// todo: use csv crate to parse csv
-///     - quote character
-///     - escape character
-///     - comment character
-///     - header
-///     - flexible with (quoted) separator
 pub fn infer_multi_char_separator(sample: &str) -> Option<String> {
-    let lines: Vec<&str> = sample.lines().collect();
+    infer_multi_char_separator_sampled(sample, DEFAULT_SAMPLE_RECORDS)
+}
 
-    if lines.len() < 2 {
+/// Like [`infer_multi_char_separator`], looking at the first `max_records` records of the sample.
+///
+/// Every substring of up to 4 characters found outside quotes, and without letters or digits, is a candidate.
+/// The candidate that splits the most records into the same number (> 1) of fields wins. On a tie,
+/// common separators go first, then longer ones: `##` over `#`, as long as every `#` is part of a `##`.
+pub fn infer_multi_char_separator_sampled(sample: &str, max_records: usize) -> Option<String> {
+    let quote_char = sniff_quote_char(sample);
+    let records: Vec<String> = RecordReader::new(sample.as_bytes(), None, quote_char)
+        .filter_map(Result::ok)
+        .map(|record| record.text)
+        .filter(|text| !text.is_empty())
+        .take(max_records)
+        .collect();
+
+    if records.len() < 2 {
         return None;
     }
 
-    let mut substr_freq: HashMap<&str, usize> = HashMap::new();
-
     // max sep length 4
-    for line in &lines {
-        for window_size in 1..=4 {
-            for i in 0..=line.len().saturating_sub(window_size) {
-                let substr = &line[i..i + window_size];
-                *substr_freq.entry(substr).or_insert(0) += 1;
+    let mut substrings: HashSet<&str> = HashSet::new();
+    for record in &records {
+        for segment in unquoted_segments(record, quote_char) {
+            for window_size in 1..=4 {
+                for i in 0..=segment.len().saturating_sub(window_size) {
+                    // skip windows past the end of short segments, or across a multi-byte character
+                    if let Some(substr) = segment.get(i..i + window_size) {
+                        substrings.insert(substr);
+                    }
+                }
             }
         }
     }
 
-    let mut candidates: Vec<&str> = substr_freq
+    let candidates: Vec<SeparatorCandidate> = substrings
+        .into_iter()
+        .filter(|substr| !substr.chars().any(|c| c.is_alphanumeric() || c == '\r' || Some(c) == quote_char))
+        .map(|sep| SeparatorCandidate::new(sep, &records, quote_char))
+        .filter(|candidate| candidate.fields > 1)
+        .collect();
+
+    let rank = |sep: &str| SEPARATOR_CANDIDATES.iter().position(|&c| c == sep).unwrap_or(SEPARATOR_CANDIDATES.len());
+    candidates
+        .iter()
+        .filter(|candidate| !candidates.iter().any(|other| other.contains(candidate)))
+        .max_by(|a, b| {
+            a.consistency
+                .total_cmp(&b.consistency)
+                .then(rank(b.separator).cmp(&rank(a.separator)))
+                .then(a.separator.len().cmp(&b.separator.len()))
+                .then(a.fields.cmp(&b.fields))
+                // the candidates come from a HashSet; keep the result stable
+                .then(b.separator.cmp(a.separator))
+        })
+        .map(|candidate| candidate.separator.to_string())
+}
+
+struct SeparatorCandidate<'a> {
+    separator: &'a str,
+    /// Number of separators outside quotes, per record.
+    counts: Vec<usize>,
+    fields: usize,
+    consistency: f64,
+}
+
+impl<'a> SeparatorCandidate<'a> {
+    fn new(separator: &'a str, records: &[String], quote_char: Option<char>) -> Self {
+        let counts: Vec<usize> = records
+            .iter()
+            .map(|record| field_offsets(record, separator, quote_char).len() - 1)
+            .collect();
+        let (fields, consistency) = field_count_consistency(records, separator, quote_char);
+        Self { separator, counts, fields, consistency }
+    }
+
+    /// Whether `other` is part of this longer, at least as consistent separator everywhere it occurs.
+    fn contains(&self, other: &SeparatorCandidate) -> bool {
+        if self.separator.len() <= other.separator.len()
+            || !self.separator.contains(other.separator)
+            || self.consistency < other.consistency
+        {
+            return false;
+        }
+        let per_separator = self.separator.matches(other.separator).count();
+        self.counts
+            .iter()
+            .zip(&other.counts)
+            .all(|(&count, &other_count)| other_count == count * per_separator)
+    }
+}
+
+/// The parts of a record outside quotes.
+fn unquoted_segments(record: &str, quote_char: Option<char>) -> Vec<&str> {
+    let Some(q) = quote_char else {
+        return vec![record];
+    };
+    // even parts are outside quotes; a doubled quote just closes and reopens the quoted part
+    record.split(q).step_by(2).collect()
+}
+
+/// How a quote is escaped inside a quoted field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapeStyle {
+    /// `"say ""hi"""`
+    Doubled,
+    /// `"say \"hi\""`
+    Backslash,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineTerminator {
+    #[default]
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl fmt::Display for LineTerminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LineTerminator::Lf => "LF",
+            LineTerminator::CrLf => "CRLF",
+            LineTerminator::Cr => "CR",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The format of a csv file, as detected by [`sniff_dialect`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dialect {
+    pub separator: String,
+    pub quote_char: Option<char>,
+    pub escape: EscapeStyle,
+    pub has_header: bool,
+    pub line_terminator: LineTerminator,
+    /// Share of the sampled records that have the most common number of fields (0.0 - 1.0);
+    /// 0.0 when no separator was found.
+    pub confidence: f64,
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "separator {:?}, quote {}, escape {:?}, header {}, line terminator {} (confidence {:.2})",
+            self.separator,
+            self.quote_char.map_or("none".to_string(), |q| format!("{:?}", q)),
+            self.escape,
+            if self.has_header { "yes" } else { "no" },
+            self.line_terminator,
+            self.confidence
+        )
+    }
+}
+
+const SEPARATOR_CANDIDATES: [&str; 5] = [",", ";", "\t", "|", ":"];
+const QUOTE_CANDIDATES: [char; 2] = ['"', '\''];
+
+/// Detect the dialect of a csv sample: separator, quote char, escape style, header and line terminator.
+/// The sample should hold complete records; a truncated last line is best left out.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::utils::csv_utils::{sniff_dialect, EscapeStyle, LineTerminator};
+///
+/// let sample = "id;name;colors\r\n1;\"Ann\";\"red;green\"\r\n2;\"Bob \"\"B\"\"\";\"blue\"\r\n";
+/// let dialect = sniff_dialect(sample);
+///
+/// assert_eq!(dialect.separator, ";");
+/// assert_eq!(dialect.quote_char, Some('"'));
+/// assert_eq!(dialect.escape, EscapeStyle::Doubled);
+/// assert_eq!(dialect.line_terminator, LineTerminator::CrLf);
+/// assert!(dialect.has_header);
+/// assert_eq!(dialect.confidence, 1.0);
+/// ```
+pub fn sniff_dialect(sample: &str) -> Dialect {
+    let line_terminator = sniff_line_terminator(sample);
+    // the record reader only splits on '\n'
+    let sample = match line_terminator {
+        LineTerminator::Cr => sample.replace('\r', "\n"),
+        _ => sample.to_string(),
+    };
+    let quote_char = sniff_quote_char(&sample);
+
+    let records: Vec<String> = RecordReader::new(sample.as_bytes(), None, quote_char)
+        .filter_map(Result::ok)
+        .map(|record| record.text)
+        .filter(|text| !text.is_empty())
+        .collect();
+
+    let mut candidates: Vec<String> = SEPARATOR_CANDIDATES.iter().map(|s| s.to_string()).collect();
+    if let Some(sep) = infer_multi_char_separator(&sample).filter(|sep| sep.len() > 1) {
+        candidates.push(sep);
+    }
+
+    // the separator that splits most records into the same number (> 1) of fields;
+    // on a tie, the most fields, then the most common separator
+    let best = candidates
+        .into_iter()
+        .enumerate()
+        .map(|(rank, sep)| {
+            let (fields, consistency) = field_count_consistency(&records, &sep, quote_char);
+            (sep, fields, consistency, rank)
+        })
+        .filter(|(_, fields, _, _)| *fields > 1)
+        .max_by(|a, b| a.2.total_cmp(&b.2).then(a.1.cmp(&b.1)).then(b.3.cmp(&a.3)));
+
+    let (separator, confidence) = match best {
+        Some((sep, _, consistency, _)) => (sep, consistency),
+        None => (",".to_string(), 0.0),
+    };
+
+    Dialect {
+        escape: sniff_escape(&records, &separator, quote_char),
+        has_header: sniff_header(&records, &separator, quote_char),
+        separator,
+        quote_char,
+        line_terminator,
+        confidence,
+    }
+}
+
+/// The sampled bytes, followed by the rest of the input.
+pub type SniffedReader<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Sniff the dialect from the first `sample_size` bytes of `reader`.
+/// Returns a reader that still yields the input from the start.
+pub fn sniff_reader<R: Read>(mut reader: R, sample_size: usize) -> io::Result<(Dialect, SniffedReader<R>)> {
+    let mut sample = Vec::with_capacity(sample_size);
+    reader.by_ref().take(sample_size as u64).read_to_end(&mut sample)?;
+
+    let text = String::from_utf8_lossy(&sample);
+    let dialect = sniff_dialect(complete_lines(&text, sample.len() == sample_size));
+
+    Ok((dialect, Cursor::new(sample).chain(reader)))
+}
+
+/// Leave out the last, possibly incomplete, line of a sample that filled the whole buffer.
+pub(crate) fn complete_lines(sample: &str, truncated: bool) -> &str {
+    match sample.rfind('\n') {
+        Some(end) if truncated => &sample[..end],
+        _ => sample,
+    }
+}
+
+fn sniff_line_terminator(sample: &str) -> LineTerminator {
+    let crlf = sample.matches("\r\n").count();
+    let lf = sample.matches('\n').count() - crlf;
+    let cr = sample.matches('\r').count() - crlf;
+
+    if crlf >= lf && crlf >= cr && crlf > 0 {
+        LineTerminator::CrLf
+    } else if cr > lf {
+        LineTerminator::Cr
+    } else {
+        LineTerminator::Lf
+    }
+}
+
+/// The quote char is the candidate found most often at the start or end of a field,
+/// i.e. next to a line break or a likely separator.
+fn sniff_quote_char(sample: &str) -> Option<char> {
+    let is_boundary = |c: Option<char>| match c {
+        None | Some('\n') | Some('\r') => true,
+        Some(c) => SEPARATOR_CANDIDATES.iter().any(|sep| sep.starts_with(c)),
+    };
+    let chars: Vec<char> = sample.chars().collect();
+
+    QUOTE_CANDIDATES
         .iter()
-        .filter(|&(_, &count)| count > 1)
-        .map(|(&substr, _)| substr)
+        .map(|&q| {
+            let count = (0..chars.len())
+                .filter(|&i| chars[i] == q)
+                .filter(|&i| {
+                    let before = i.checked_sub(1).map(|j| chars[j]);
+                    is_boundary(before) || is_boundary(chars.get(i + 1).copied())
+                })
+                .count();
+            (q, count)
+        })
+        .filter(|&(_, count)| count > 0)
+        .max_by_key(|&(_, count)| count)
+        .map(|(q, _)| q)
+}
+
+/// The most common number of fields, and the share of the records that have it.
+fn field_count_consistency(records: &[String], separator: &str, quote_char: Option<char>) -> (usize, f64) {
+    let mut frequencies: HashMap<usize, usize> = HashMap::new();
+    for record in records {
+        *frequencies
+            .entry(field_offsets(record, separator, quote_char).len())
+            .or_insert(0) += 1;
+    }
+
+    frequencies
+        .into_iter()
+        .max_by_key(|&(fields, count)| (count, fields))
+        .map_or((0, 0.0), |(fields, count)| (fields, count as f64 / records.len() as f64))
+}
+
+/// Backslash escapes win when `\"` is more common than a doubled quote inside a field.
+fn sniff_escape(records: &[String], separator: &str, quote_char: Option<char>) -> EscapeStyle {
+    let Some(q) = quote_char else {
+        return EscapeStyle::Doubled;
+    };
+    let (escaped, doubled) = (format!("\\{}", q), format!("{}{}", q, q));
+
+    let mut backslash = 0;
+    let mut double = 0;
+    for record in records {
+        backslash += record.matches(&escaped).count();
+        // an empty field ("") is not an escaped quote
+        double += record
+            .match_indices(&doubled)
+            .filter(|&(i, _)| {
+                let empty_field = (i == 0 || record[..i].ends_with(separator))
+                    && (i + 2 == record.len() || record[i + 2..].starts_with(separator));
+                !empty_field
+            })
+            .count();
+    }
+
+    if backslash > double { EscapeStyle::Backslash } else { EscapeStyle::Doubled }
+}
+
+/// Like Python's `csv.Sniffer`: every column votes. A column of numbers, or of values of the same length,
+/// votes for a header when the first value differs from the rest, and against it otherwise.
+fn sniff_header(records: &[String], separator: &str, quote_char: Option<char>) -> bool {
+    let rows: Vec<Vec<String>> = records
+        .iter()
+        .filter_map(|record| line_processor(record, separator, quote_char).ok())
         .collect();
+    let Some((header, data)) = rows.split_first() else {
+        return false;
+    };
+    if data.is_empty() || header.iter().any(|name| name.trim().is_empty()) {
+        return false;
+    }
 
-    candidates.sort_by_key(|b| std::cmp::Reverse(b.len()));
+    let is_number = |value: &str| value.trim().parse::<f64>().is_ok();
+    let mut votes = 0i32;
 
-    for candidate in candidates {
-        let counts: Vec<usize> = lines
+    for (i, name) in header.iter().enumerate() {
+        let values: Vec<&str> = data
             .iter()
-            .map(|line| line.matches(candidate).count())
+            .filter(|row| row.len() == header.len())
+            .map(|row| row[i].as_str())
             .collect();
-        if counts.windows(2).all(|w| w[0] == w[1] && w[0] > 0) {
-            return Some(candidate.to_string());
+        if values.is_empty() {
+            continue;
+        }
+
+        if values.iter().all(|v| is_number(v)) {
+            votes += if is_number(name) { -1 } else { 1 };
+        } else {
+            let length = values[0].chars().count();
+            if values.iter().all(|v| v.chars().count() == length) {
+                votes += if name.chars().count() != length { 1 } else { -1 };
+            }
         }
     }
 
-    None
+    votes > 0
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_field_offsets() {
+        assert_eq!(field_offsets("", ",", Some('"')), vec![0]);
+        assert_eq!(field_offsets("a,,b", ",", Some('"')), vec![0, 2, 3]);
+        // escaped quotes and separators inside quotes
+        assert_eq!(field_offsets(r#""say ""hi"", you",x"#, ",", Some('"')), vec![0, 18]);
+        // a quote in the middle of an unquoted field is just a character
+        assert_eq!(field_offsets(r#"a"b,c"#, ",", Some('"')), vec![0, 4]);
+        assert_eq!(field_offsets("a$$$\"b$$$c\"$$$d", "$$$", Some('"')), vec![0, 4, 14]);
+        assert_eq!(field_offsets("é;ü", ";", None), vec![0, 3]);
+    }
+
+    #[test]
+    fn test_sniff_dialect() {
+        let dialect = sniff_dialect(&std::fs::read_to_string("../../examples/semi_quoted_with_header.csv").unwrap());
+        assert_eq!(dialect.separator, ",");
+        assert_eq!(dialect.quote_char, Some('"'));
+        assert!(dialect.has_header);
+        assert_eq!(dialect.line_terminator, LineTerminator::Lf);
+
+        let dialect = sniff_dialect("1|'a \\'b\\''|3.5\r2|'c'|4.0\r3|'d'|4.5\r");
+        assert_eq!(dialect.separator, "|");
+        assert_eq!(dialect.quote_char, Some('\''));
+        assert_eq!(dialect.escape, EscapeStyle::Backslash);
+        assert_eq!(dialect.line_terminator, LineTerminator::Cr);
+        assert!(!dialect.has_header);
+
+        let dialect = sniff_dialect("a##b##c\n1##2##3\n");
+        assert_eq!(dialect.separator, "##");
+
+        let dialect = sniff_dialect("single\ncolumn\n");
+        assert_eq!(dialect.confidence, 0.0);
+    }
+
+    #[test]
+    fn test_sniff_reader() {
+        let data = "a;b\n1;2\n3;4\n5;6";
+        let (dialect, mut reader) = sniff_reader(data.as_bytes(), 10).unwrap();
+        assert_eq!(dialect.separator, ";");
+
+        // nothing is lost
+        let mut all = String::new();
+        reader.read_to_string(&mut all).unwrap();
+        assert_eq!(all, data);
+    }
+
     #[test]
     fn test_infer_multi_char_separator() {
         let sample = "a,b,c\n1,2,3\n4,5,6";
@@ -250,4 +650,23 @@ mod tests {
         let result = infer_multi_char_separator(sample);
         assert_eq!(result.unwrap(), "#@#");
     }
+
+    #[test]
+    fn test_infer_multi_char_separator_quoted() {
+        let sample = std::fs::read_to_string("../../examples/full_quoted_with_header_semicolon.csv").unwrap();
+        assert_eq!(infer_multi_char_separator(&sample).unwrap(), ";");
+
+        // one outlier record, and dates whose dashes are just as consistent as the separator
+        let sample = "day,amount\n2024-01-01,1\n2024-01-02,2\n2024-01-03\n2024-01-04,4";
+        assert_eq!(infer_multi_char_separator(sample).unwrap(), ",");
+
+        // a bar inside a double bar is no separator of its own
+        let sample = "a||b||\"x|y\"\n1||2||3\n4||5||6";
+        assert_eq!(infer_multi_char_separator(sample).unwrap(), "||");
+
+        // only the sampled records count
+        let sample = "a,b\n1,2\nx;y;z\nx;y;z\nx;y;z";
+        assert_eq!(infer_multi_char_separator_sampled(sample, 2).unwrap(), ",");
+        assert_eq!(infer_multi_char_separator(sample).unwrap(), ";");
+    }
 }
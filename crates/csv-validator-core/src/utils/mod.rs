@@ -1 +1,5 @@
 pub mod csv_utils;
+pub mod paths;
+pub mod profile;
+pub(crate) mod temp_file;
+pub mod tokenizer;
@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use glob::MatchOptions;
+
+/// A file to validate, found directly or in a directory or with a glob pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputFile {
+    pub path: PathBuf,
+    /// The path of the file relative to the directory it was found in, or to the part of the glob pattern
+    /// before the first wildcard; the file name for a file given as such. Used to name the output files.
+    pub name: PathBuf,
+}
+
+/// Expand files, directories and glob patterns into the files they name, in the order given.
+/// Directories are read recursively, in name order; hidden files and directories (names starting
+/// with `.`) are left out, unless given explicitly. A file found twice is only listed once.
+///
+/// # Example
+///
+/// ```
+/// use std::path::Path;
+/// use csv_validator_core::utils::paths::expand_inputs;
+///
+/// let inputs = expand_inputs(&["../../examples/typed_with_*.csv".to_string()]).unwrap();
+///
+/// assert_eq!(inputs[0].path, Path::new("../../examples/typed_with_header.csv"));
+/// assert_eq!(inputs[0].name, Path::new("typed_with_header.csv"));
+/// ```
+pub fn expand_inputs(inputs: &[String]) -> io::Result<Vec<InputFile>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            walk_dir(path, path, &mut files)?;
+        } else if path.exists() {
+            let name = path.file_name().map_or_else(|| path.to_path_buf(), PathBuf::from);
+            files.push(InputFile { path: path.to_path_buf(), name });
+        } else if input.contains(['*', '?', '[']) {
+            expand_glob(input, &mut files)?;
+        } else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{}: no such file or directory", input)));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.path.clone()));
+    Ok(files)
+}
+
+fn expand_glob(pattern: &str, files: &mut Vec<InputFile>) -> io::Result<()> {
+    let options = MatchOptions {
+        require_literal_leading_dot: true,
+        ..MatchOptions::new()
+    };
+    let paths = glob::glob_with(pattern, options)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", pattern, e)))?;
+
+    // names are relative to the components before the first wildcard
+    let base: PathBuf = Path::new(pattern)
+        .components()
+        .take_while(|component| !component.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect();
+
+    let count = files.len();
+    for path in paths {
+        let path = path.map_err(io::Error::from)?;
+        if path.is_dir() {
+            walk_dir(&path, &base, files)?;
+        } else {
+            let name = path.strip_prefix(&base).unwrap_or(&path).to_path_buf();
+            files.push(InputFile { path, name });
+        }
+    }
+    if files.len() == count {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("no files match '{}'", pattern)));
+    }
+    Ok(())
+}
+
+fn walk_dir(dir: &Path, base: &Path, files: &mut Vec<InputFile>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            walk_dir(&path, base, files)?;
+        } else {
+            let name = path.strip_prefix(base).unwrap_or(&path).to_path_buf();
+            files.push(InputFile { path, name });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory tree with a few empty files, removed afterwards.
+    struct Tree(PathBuf);
+
+    impl Tree {
+        fn new(name: &str, files: &[&str]) -> Self {
+            let root = std::env::temp_dir().join(format!("csv-validate-{}-{}", name, std::process::id()));
+            for file in files {
+                let path = root.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "").unwrap();
+            }
+            Tree(root)
+        }
+
+        fn path(&self, path: &str) -> String {
+            self.0.join(path).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn names(inputs: &[String]) -> Vec<String> {
+        expand_inputs(inputs)
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_dirs() {
+        let tree = Tree::new("dirs", &["b.csv", "a/2.csv", "a/1.csv", ".hidden.csv", ".git/x", "c/.d/3.csv"]);
+
+        assert_eq!(names(&[tree.path("")]), ["a/1.csv", "a/2.csv", "b.csv"]);
+        assert_eq!(names(&[tree.path("a")]), ["1.csv", "2.csv"]);
+        // explicit files keep their name, even hidden ones, and aren't listed twice
+        let inputs = [tree.path(".hidden.csv"), tree.path("a"), tree.path("a/1.csv")];
+        assert_eq!(names(&inputs), [".hidden.csv", "1.csv", "2.csv"]);
+
+        let missing = expand_inputs(&[tree.path("missing.csv")]).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_expand_globs() {
+        let files = ["day=1/part-0.csv", "day=1/part-1.csv.gz", "day=2/part-0.csv", "day=2/.part-1.csv"];
+        let tree = Tree::new("globs", &files);
+
+        assert_eq!(names(&[tree.path("day=*/part-*")]), files[..3]);
+        assert_eq!(names(&[tree.path("day=2/*.csv")]), ["part-0.csv"]);
+        // directories that match are read
+        assert_eq!(names(&[tree.path("day=[2]")]), ["day=2/part-0.csv"]);
+
+        let none = expand_inputs(&[tree.path("day=3/*.csv")]).unwrap_err();
+        assert!(none.to_string().starts_with("no files match"), "{}", none);
+        assert!(expand_inputs(&[tree.path("day=[")]).is_err());
+    }
+}
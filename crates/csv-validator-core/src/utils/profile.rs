@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+use encoding_rs::{Encoding, UTF_8};
+
+use crate::readers::csv_readers::RecordReader;
+use crate::utils::csv_utils::{Dialect, LineTerminator, complete_lines, line_processor, sniff_dialect};
+use crate::validators::column_validators::{ColumnSpec, infer_column};
+
+/// What a sample of a csv file looks like: its encoding, dialect, shape and column types.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub encoding: &'static str,
+    pub dialect: Dialect,
+    /// Number of records sampled, the header included.
+    pub records: usize,
+    /// Number of records per number of fields, most common first.
+    pub field_counts: Vec<(usize, usize)>,
+    pub header: Option<Vec<String>>,
+    /// One entry per column of the most common record layout.
+    pub columns: Vec<ColumnSpec>,
+}
+
+/// Guess the encoding of a sample from its byte order mark, or else from whether it is valid UTF-8.
+/// Anything else is assumed to be windows-1252, the most common legacy encoding.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::utils::profile::detect_encoding;
+///
+/// assert_eq!(detect_encoding(b"\xEF\xBB\xBFa,b"), "utf-8");
+/// assert_eq!(detect_encoding(b"\xFF\xFEa\x00"), "utf-16le");
+/// assert_eq!(detect_encoding("caf\u{e9}".as_bytes()), "utf-8");
+/// assert_eq!(detect_encoding(b"caf\xE9 au lait"), "windows-1252");
+/// ```
+pub fn detect_encoding(sample: &[u8]) -> &'static str {
+    match sample {
+        [0xEF, 0xBB, 0xBF, ..] => "utf-8",
+        [0xFF, 0xFE, ..] => "utf-16le",
+        [0xFE, 0xFF, ..] => "utf-16be",
+        _ => match std::str::from_utf8(sample) {
+            Ok(_) => "utf-8",
+            // a multi-byte character cut off at the end of the sample
+            Err(e) if e.error_len().is_none() => "utf-8",
+            Err(_) => "windows-1252",
+        },
+    }
+}
+
+/// Decode a sample for profiling; characters that can't be decoded become U+FFFD.
+fn decode(sample: &[u8], encoding: &str) -> String {
+    let encoding = Encoding::for_label(encoding.as_bytes()).unwrap_or(UTF_8);
+    encoding.decode_with_bom_removal(sample).0.into_owned()
+}
+
+/// Profile a sample of a csv file. Set `truncated` when the sample was cut off at an arbitrary byte,
+/// so its last line is left out.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::utils::profile::profile_sample;
+///
+/// let profile = profile_sample(b"id;price;day\n1;2.50;2024-01-31\n2;;2024-02-01\n", false);
+///
+/// assert_eq!(profile.dialect.separator, ";");
+/// assert_eq!(profile.field_counts, vec![(3, 3)]);
+/// assert_eq!(profile.columns[1].name, "price");
+/// assert_eq!(profile.columns[1].column_type.to_string(), "decimal");
+/// assert!(profile.columns[1].nullable);
+/// assert_eq!(profile.columns[2].column_type.to_string(), "date (%Y-%m-%d)");
+/// ```
+pub fn profile_sample(sample: &[u8], truncated: bool) -> Profile {
+    let encoding = detect_encoding(sample);
+    let text = decode(sample, encoding);
+    let text = complete_lines(&text, truncated);
+    let dialect = sniff_dialect(text);
+
+    // the record reader only splits on '\n'
+    let text = match dialect.line_terminator {
+        LineTerminator::Cr => text.replace('\r', "\n"),
+        _ => text.to_string(),
+    };
+    let mut rows: Vec<Vec<String>> = RecordReader::new(text.as_bytes(), Some(&dialect.separator), dialect.quote_char)
+        .filter_map(Result::ok)
+        .filter(|record| !record.text.is_empty())
+        .filter_map(|record| line_processor(&record.text, &dialect.separator, dialect.quote_char).ok())
+        .collect();
+    let records = rows.len();
+
+    let mut frequencies: HashMap<usize, usize> = HashMap::new();
+    for row in &rows {
+        *frequencies.entry(row.len()).or_insert(0) += 1;
+    }
+    let mut field_counts: Vec<(usize, usize)> = frequencies.into_iter().collect();
+    field_counts.sort_by_key(|&(fields, count)| (std::cmp::Reverse(count), fields));
+    let width = field_counts.first().map_or(0, |&(fields, _)| fields);
+
+    let header = (dialect.has_header && !rows.is_empty()).then(|| rows.remove(0));
+    let columns = (0..width)
+        .map(|i| {
+            let name = header
+                .as_ref()
+                .and_then(|header| header.get(i).cloned())
+                .unwrap_or_else(|| format!("column_{}", i + 1));
+            let values: Vec<&str> = rows
+                .iter()
+                .filter_map(|row| row.get(i).map(String::as_str))
+                .collect();
+            infer_column(&name, &values)
+        })
+        .collect();
+
+    Profile {
+        encoding,
+        dialect,
+        records,
+        field_counts,
+        header,
+        columns,
+    }
+}
+
+/// Profile the first `sample_size` bytes of `reader`.
+pub fn profile_reader<R: Read>(reader: R, sample_size: usize) -> io::Result<Profile> {
+    let mut sample = Vec::with_capacity(sample_size);
+    reader.take(sample_size as u64).read_to_end(&mut sample)?;
+    Ok(profile_sample(&sample, sample.len() == sample_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_example() {
+        let file = std::fs::File::open("../../examples/typed_with_header.csv").unwrap();
+        let profile = profile_reader(file, 1024 * 1024).unwrap();
+
+        assert_eq!(profile.encoding, "utf-8");
+        assert_eq!(profile.dialect.separator, ";");
+        assert_eq!(profile.header.as_ref().unwrap()[0], "string_1");
+        let types: Vec<String> = profile.columns.iter().map(|c| c.column_type.to_string()).collect();
+        // the invalid values in the example make their columns less specific
+        assert_eq!(types[0], "string");
+        assert_eq!(types[3], "decimal");
+    }
+
+    #[test]
+    fn test_profile_utf16_without_header() {
+        let text = "1\t2020-01-01\r\n2\t2020-01-02\r\n";
+        let mut sample = vec![0xFF, 0xFE];
+        sample.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+
+        let profile = profile_sample(&sample, false);
+        assert_eq!(profile.encoding, "utf-16le");
+        assert_eq!(profile.dialect.separator, "\t");
+        assert_eq!(profile.dialect.line_terminator, LineTerminator::CrLf);
+        assert!(profile.header.is_none());
+        assert_eq!(profile.columns[0].name, "column_1");
+        assert_eq!(profile.columns[0].column_type.to_string(), "integer");
+    }
+}
@@ -0,0 +1,36 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A file in the temp directory, removed when it is dropped.
+pub(crate) struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Create `csv-validate-{kind}-{pid}-{n}.{extension}`, unique within the process.
+    pub(crate) fn create(kind: &str, extension: &str) -> io::Result<(Self, File)> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "csv-validate-{}-{}-{}.{}",
+            kind,
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed),
+            extension
+        );
+        let path = std::env::temp_dir().join(name);
+        let file = File::create(&path)?;
+        Ok((Self { path }, file))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
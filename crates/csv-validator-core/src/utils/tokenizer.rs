@@ -0,0 +1,353 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use memchr::{memchr, memchr2};
+
+use crate::utils::csv_utils::LineTerminator;
+
+/// The fields of a record, as byte ranges into it: splitting a record copies nothing.
+/// Separators inside quoted fields don't count, and a doubled quote inside a quoted field is an escaped quote.
+/// A quote in the middle of an unquoted field is just a character.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::utils::tokenizer::Fields;
+///
+/// let fields = Fields::new(r#"1;"red;""green""";x"#, ";", Some('"'));
+///
+/// assert_eq!(fields.len(), 3);
+/// assert_eq!(fields.raw(1), Some(r#""red;""green""""#));
+/// assert_eq!(fields.get(1).unwrap(), r#"red;"green""#);
+/// assert_eq!(fields.iter().collect::<Vec<_>>(), ["1", r#"red;"green""#, "x"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Fields<'a> {
+    record: &'a str,
+    separator: &'a str,
+    quote_char: Option<char>,
+    ranges: Vec<Range<usize>>,
+}
+
+impl<'a> Fields<'a> {
+    pub fn new(record: &'a str, separator: &'a str, quote_char: Option<char>) -> Self {
+        let mut ranges = Vec::new();
+        split_fields(record, separator, quote_char, &mut ranges);
+        Self {
+            record,
+            separator,
+            quote_char,
+            ranges,
+        }
+    }
+
+    /// These fields if they were split with `separator` and `quote_char`, or the record split with them.
+    /// The engine splits every row once, with the dialect of the input, for all validators: only a validator
+    /// configured with another separator or quote char splits it again.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::borrow::Cow;
+    ///
+    /// use csv_validator_core::utils::tokenizer::Fields;
+    ///
+    /// let fields = Fields::new("a;b,c", ",", Some('"'));
+    /// assert!(matches!(fields.split_as(",", Some('"')), Cow::Borrowed(_)));
+    /// assert_eq!(fields.split_as(";", Some('"')).len(), 2);
+    /// ```
+    pub fn split_as<'b>(&'b self, separator: &'b str, quote_char: Option<char>) -> Cow<'b, Fields<'b>> {
+        if self.separator == separator && self.quote_char == quote_char {
+            Cow::Borrowed(self)
+        } else {
+            Cow::Owned(Fields::new(self.record, separator, quote_char))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Byte ranges of the fields in the record, quotes included.
+    pub fn ranges(&self) -> &[Range<usize>] {
+        &self.ranges
+    }
+
+    /// The field as it is in the record, quotes included.
+    pub fn raw(&self, i: usize) -> Option<&'a str> {
+        self.ranges.get(i).map(|range| &self.record[range.clone()])
+    }
+
+    /// The value of the field: without its quotes, and with escaped quotes unescaped.
+    /// Only a field with escaped quotes is copied.
+    pub fn get(&self, i: usize) -> Option<Cow<'a, str>> {
+        self.raw(i).map(|field| unquote(field, self.quote_char))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Cow<'a, str>> + '_ {
+        (0..self.len()).filter_map(|i| self.get(i))
+    }
+}
+
+/// Split `record` into `ranges` (cleared first), so a caller can reuse the allocation across records.
+/// An empty separator leaves the record whole.
+pub fn split_fields(record: &str, separator: &str, quote_char: Option<char>, ranges: &mut Vec<Range<usize>>) {
+    ranges.clear();
+    let bytes = record.as_bytes();
+    let sep = separator.as_bytes();
+    let mut quote_buf = [0; 4];
+    let quote = quote_char.map(|q| q.encode_utf8(&mut quote_buf).as_bytes());
+
+    let Some(&sep_start) = sep.first() else {
+        ranges.push(0..bytes.len());
+        return;
+    };
+
+    let mut field_start = 0;
+    let mut pos = 0;
+    let mut in_quotes = false;
+
+    while pos < bytes.len() {
+        if in_quotes {
+            // quote is set whenever in_quotes is
+            let q = quote.unwrap_or_default();
+            let Some(i) = find(bytes, pos, q) else {
+                // an unterminated quoted field runs to the end of the record
+                break;
+            };
+            pos = i + q.len();
+            if bytes[pos..].starts_with(q) {
+                // a doubled quote is an escaped quote and keeps the field open
+                pos += q.len();
+            } else {
+                in_quotes = false;
+            }
+            continue;
+        }
+
+        let next = match quote {
+            Some(q) => memchr2(sep_start, q[0], &bytes[pos..]),
+            None => memchr(sep_start, &bytes[pos..]),
+        };
+        let Some(i) = next.map(|i| pos + i) else {
+            break;
+        };
+
+        if let Some(q) = quote
+            && bytes[i..].starts_with(q)
+        {
+            in_quotes = i == field_start;
+            pos = i + q.len();
+        } else if bytes[i..].starts_with(sep) {
+            ranges.push(field_start..i);
+            field_start = i + sep.len();
+            pos = field_start;
+        } else {
+            pos = i + 1;
+        }
+    }
+
+    ranges.push(field_start..bytes.len());
+}
+
+/// Finds where records end: at a line break outside quoted fields. Like in [`split_fields`], a quote only
+/// opens a quoted field at the start of a field, so a quote in the middle of an unquoted field doesn't
+/// swallow the line breaks after it. Without a separator (while it's being detected), a field is taken to
+/// start after any punctuation or whitespace. Line breaks are LF (CRLF ends in one), or CR with
+/// [`RecordEnds::line_terminator`].
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::utils::csv_utils::LineTerminator;
+/// use csv_validator_core::utils::tokenizer::RecordEnds;
+///
+/// let mut ends = RecordEnds::new(Some(","), Some('"'));
+/// assert_eq!(ends.find(b"1,5\" screen\n2,b\n"), Some(11));
+///
+/// // a quoted field runs on, into the next lines
+/// assert_eq!(ends.find(b"1,\"a\n"), None);
+/// assert_eq!(ends.find(b"b\",c\n"), Some(4));
+///
+/// let mut ends = RecordEnds::new(Some(","), Some('"')).line_terminator(LineTerminator::Cr);
+/// assert_eq!(ends.find(b"1,\"a\rb\"\r2,c\r"), Some(7));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RecordEnds {
+    separator: Option<Vec<u8>>,
+    quote: Option<Vec<u8>>,
+    /// The last byte of a line break.
+    terminator: u8,
+    in_quotes: bool,
+}
+
+impl RecordEnds {
+    pub fn new(separator: Option<&str>, quote_char: Option<char>) -> Self {
+        let mut quote_buf = [0; 4];
+        Self {
+            separator: separator.filter(|sep| !sep.is_empty()).map(|sep| sep.as_bytes().to_vec()),
+            quote: quote_char.map(|q| q.encode_utf8(&mut quote_buf).as_bytes().to_vec()),
+            terminator: b'\n',
+            in_quotes: false,
+        }
+    }
+
+    /// End the lines at CR alone with `LineTerminator::Cr`; LF and CRLF lines both end at the LF.
+    pub fn line_terminator(mut self, line_terminator: LineTerminator) -> Self {
+        self.terminator = match line_terminator {
+            LineTerminator::Cr => b'\r',
+            LineTerminator::Lf | LineTerminator::CrLf => b'\n',
+        };
+        self
+    }
+
+    /// The last byte of a line break.
+    pub fn terminator(&self) -> u8 {
+        self.terminator
+    }
+
+    /// Start inside a quoted field, for a piece of the input that starts at a line break in the middle of one.
+    pub(crate) fn in_quotes(mut self, in_quotes: bool) -> Self {
+        self.in_quotes = in_quotes;
+        self
+    }
+
+    /// Whether the bytes given so far end inside a quoted field.
+    pub(crate) fn is_in_quotes(&self) -> bool {
+        self.in_quotes
+    }
+
+    /// The position of the line break that ends the current record in `bytes`, if there is one.
+    /// `bytes` start a record, or continue it from where the last call left off (at the start of a line).
+    pub fn find(&mut self, bytes: &[u8]) -> Option<usize> {
+        let Some(q) = self.quote.as_deref() else {
+            return memchr(self.terminator, bytes);
+        };
+        let mut pos = 0;
+        loop {
+            if self.in_quotes {
+                pos = find(bytes, pos, q)? + q.len();
+                if bytes[pos..].starts_with(q) {
+                    // a doubled quote is an escaped quote and keeps the field open
+                    pos += q.len();
+                } else {
+                    self.in_quotes = false;
+                }
+                continue;
+            }
+
+            let i = pos + memchr2(self.terminator, q[0], &bytes[pos..])?;
+            if bytes[i] == self.terminator {
+                return Some(i);
+            }
+            if bytes[i..].starts_with(q) {
+                self.in_quotes = self.field_starts_at(bytes, i);
+                pos = i + q.len();
+            } else {
+                pos = i + 1;
+            }
+        }
+    }
+
+    /// Whether a field starts at `i`, outside quoted fields: at the start of a line, or after a separator.
+    fn field_starts_at(&self, bytes: &[u8], i: usize) -> bool {
+        let Some(&before) = i.checked_sub(1).map(|j| &bytes[j]) else {
+            return true;
+        };
+        match &self.separator {
+            _ if before == self.terminator => true,
+            Some(sep) => bytes[..i].ends_with(sep),
+            None => {
+                let after_quote = self.quote.as_ref().is_some_and(|q| bytes[..i].ends_with(q));
+                (before.is_ascii_punctuation() || before.is_ascii_whitespace()) && !after_quote
+            }
+        }
+    }
+}
+
+/// The first occurrence of `needle` in `haystack`, from `from` on.
+fn find(haystack: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    let mut pos = from;
+    while let Some(i) = memchr(needle[0], &haystack[pos..]) {
+        if haystack[pos + i..].starts_with(needle) {
+            return Some(pos + i);
+        }
+        pos += i + 1;
+    }
+    None
+}
+
+/// Strip the quotes of a quoted field and unescape doubled quotes; other fields are returned as is.
+pub fn unquote(field: &str, quote_char: Option<char>) -> Cow<'_, str> {
+    let Some(q) = quote_char else {
+        return Cow::Borrowed(field);
+    };
+    let inner = field.strip_prefix(q).and_then(|rest| rest.strip_suffix(q));
+    match inner {
+        // inside a quoted field, quotes can only be escaped (doubled) ones
+        Some(inner) if inner.contains(q) => Cow::Owned(inner.replace(&q.to_string().repeat(2), &q.to_string())),
+        Some(inner) => Cow::Borrowed(inner),
+        None => Cow::Borrowed(field),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values<'a>(record: &'a str, separator: &'a str, quote_char: Option<char>) -> Vec<Cow<'a, str>> {
+        Fields::new(record, separator, quote_char).iter().collect()
+    }
+
+    #[test]
+    fn test_fields() {
+        assert_eq!(values("", ",", Some('"')), [""]);
+        assert_eq!(values("a,,b,", ",", Some('"')), ["a", "", "b", ""]);
+        assert_eq!(values("a$$b$c$$", "$$", Some('"')), ["a", "b$c", ""]);
+        // a quote inside an unquoted field, and an unterminated quoted field
+        assert_eq!(values(r#"5" screen,"a,b"#, ",", Some('"')), [r#"5" screen"#, r#""a,b"#]);
+        // a line break inside quotes, and text after the closing quote
+        assert_eq!(values("\"a\nb\"x,c", ",", Some('"')), ["\"a\nb\"x", "c"]);
+        assert_eq!(values(r#""a,b",c"#, ",", None), [r#""a"#, r#"b""#, "c"]);
+        // multi-byte separator and quote
+        assert_eq!(values("¤a¦b¤¦c", "¦", Some('¤')), ["a¦b", "c"]);
+    }
+
+    /// The records of `data`, as split by [`RecordEnds`].
+    fn records<'a>(data: &'a str, separator: Option<&str>) -> Vec<&'a str> {
+        let mut ends = RecordEnds::new(separator, Some('"'));
+        let mut records = Vec::new();
+        let mut start = 0;
+        while let Some(end) = ends.find(&data.as_bytes()[start..]) {
+            records.push(&data[start..start + end]);
+            start += end + 1;
+        }
+        records
+    }
+
+    #[test]
+    fn test_record_ends() {
+        // quotes in the middle of unquoted fields are characters, also without a known separator
+        let data = "id,name\n1,5\" screen\n2,\"a\"\"\nb\"\n3,c\n";
+        let expected = ["id,name", "1,5\" screen", "2,\"a\"\"\nb\"", "3,c"];
+        assert_eq!(records(data, Some(",")), expected);
+        assert_eq!(records(data, None), expected);
+        // a quote after a space isn't at the start of a field, unless the separator is unknown
+        assert_eq!(records("a, \"b\nc\"\n", Some(",")), ["a, \"b", "c\""]);
+        assert_eq!(records("a, \"b\nc\"\n", None), ["a, \"b\nc\""]);
+        assert_eq!(records("a||\"b\nc\"\n", Some("||")), ["a||\"b\nc\""]);
+    }
+
+    #[test]
+    fn test_fields_borrowed() {
+        let fields = Fields::new(r#""a","b""c""#, ",", Some('"'));
+        assert!(matches!(fields.get(0), Some(Cow::Borrowed("a"))));
+        assert!(matches!(fields.get(1), Some(Cow::Owned(_))));
+        assert_eq!(fields.ranges(), [0..3, 4..10]);
+        assert!(fields.get(2).is_none());
+    }
+}
@@ -0,0 +1,604 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::config::CommonConfig;
+use crate::utils::csv_utils::line_processor;
+use crate::utils::tokenizer::Fields;
+use crate::validators::{Accumulator, Validator};
+use crate::validators::issue::{Policy, ValidationIssue, ValidationResult};
+
+/// The expected layout of a CSV file: one entry per column, in file order.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::validators::column_validators::Schema;
+///
+/// let schema: Schema = serde_yaml::from_str(r#"
+/// columns:
+///   - name: id
+///     type: integer
+///   - name: price
+///     type: decimal
+///     precision: 6
+///     scale: 2
+///   - name: created
+///     type: datetime
+///     format: "%Y-%m-%d %H:%M:%S"
+/// "#).unwrap();
+///
+/// assert_eq!(schema.columns.len(), 3);
+/// assert!(schema.columns[1].check("1234.50").is_ok());
+/// assert!(schema.columns[1].check("1234.505").is_err());
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Schema {
+    pub columns: Vec<ColumnSpec>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ColumnSpec {
+    pub name: String,
+    #[serde(flatten)]
+    pub column_type: ColumnType,
+    /// Accept empty values, whatever the type.
+    #[serde(default)]
+    pub nullable: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ColumnType {
+    String,
+    Integer,
+    Decimal {
+        /// Maximum number of significant digits, `scale` of which are reserved for the fraction.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        precision: Option<usize>,
+        /// Maximum number of digits after the decimal point.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        scale: Option<usize>,
+    },
+    Boolean {
+        #[serde(default = "default_truthy")]
+        truthy: Vec<String>,
+        #[serde(default = "default_falsy")]
+        falsy: Vec<String>,
+    },
+    Date {
+        #[serde(default = "default_date_format")]
+        format: String,
+    },
+    #[serde(rename = "datetime")]
+    DateTime {
+        #[serde(default = "default_datetime_format")]
+        format: String,
+    },
+}
+
+fn default_truthy() -> Vec<String> {
+    ["true", "t", "yes", "y", "1"].map(String::from).to_vec()
+}
+
+fn default_falsy() -> Vec<String> {
+    ["false", "f", "no", "n", "0"].map(String::from).to_vec()
+}
+
+fn default_date_format() -> String {
+    "%Y-%m-%d".to_string()
+}
+
+fn default_datetime_format() -> String {
+    "%Y-%m-%d %H:%M:%S".to_string()
+}
+
+impl ColumnSpec {
+    /// Check a single (unquoted) value against the column definition.
+    /// Returns a short description of what's wrong with the value.
+    pub fn check(&self, value: &str) -> Result<(), String> {
+        if value.is_empty() && self.nullable {
+            return Ok(());
+        }
+        self.column_type.check(value)
+    }
+}
+
+impl ColumnType {
+    pub fn check(&self, value: &str) -> Result<(), String> {
+        match self {
+            ColumnType::String => Ok(()),
+            ColumnType::Integer => value
+                .parse::<i64>()
+                .map(|_| ())
+                .map_err(|_| "not an integer".to_string()),
+            ColumnType::Decimal { precision, scale } => check_decimal(value, *precision, *scale),
+            ColumnType::Boolean { truthy, falsy } => {
+                if truthy
+                    .iter()
+                    .chain(falsy)
+                    .any(|token| token.eq_ignore_ascii_case(value))
+                {
+                    Ok(())
+                } else {
+                    Err("not a boolean".to_string())
+                }
+            }
+            ColumnType::Date { format } => NaiveDate::parse_from_str(value, format)
+                .map(|_| ())
+                .map_err(|_| format!("not a date in format '{}'", format)),
+            ColumnType::DateTime { format } => NaiveDateTime::parse_from_str(value, format)
+                .map(|_| ())
+                .map_err(|_| format!("not a datetime in format '{}'", format)),
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColumnType::String => write!(f, "string"),
+            ColumnType::Integer => write!(f, "integer"),
+            ColumnType::Decimal { precision: Some(p), scale: Some(s) } => write!(f, "decimal({}, {})", p, s),
+            ColumnType::Decimal { .. } => write!(f, "decimal"),
+            ColumnType::Boolean { .. } => write!(f, "boolean"),
+            ColumnType::Date { format } => write!(f, "date ({})", format),
+            ColumnType::DateTime { format } => write!(f, "datetime ({})", format),
+        }
+    }
+}
+
+const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%d/%m/%Y", "%Y/%m/%d"];
+const DATETIME_FORMATS: [&str; 3] = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%d/%m/%Y %H:%M:%S"];
+
+/// Infer the most specific column definition that accepts all `values`:
+/// integer, decimal, boolean, date, datetime or else string. Empty values make the column nullable.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::validators::column_validators::{infer_column, ColumnType};
+///
+/// let column = infer_column("price", &["1.5", "", "20"]);
+/// assert!(matches!(column.column_type, ColumnType::Decimal { .. }));
+/// assert!(column.nullable);
+///
+/// let column = infer_column("created", &["31/12/2020", "01/01/2021"]);
+/// assert_eq!(column.column_type.to_string(), "date (%d/%m/%Y)");
+/// ```
+pub fn infer_column(name: &str, values: &[&str]) -> ColumnSpec {
+    let nullable = values.iter().any(|v| v.is_empty());
+    let values: Vec<&str> = values.iter().copied().filter(|v| !v.is_empty()).collect();
+
+    let mut candidates = vec![
+        ColumnType::Integer,
+        ColumnType::Decimal { precision: None, scale: None },
+        ColumnType::Boolean { truthy: default_truthy(), falsy: default_falsy() },
+    ];
+    candidates.extend(DATE_FORMATS.map(|format| ColumnType::Date { format: format.to_string() }));
+    candidates.extend(DATETIME_FORMATS.map(|format| ColumnType::DateTime { format: format.to_string() }));
+
+    let column_type = if values.is_empty() {
+        ColumnType::String
+    } else {
+        candidates
+            .into_iter()
+            .find(|candidate| values.iter().all(|v| candidate.check(v).is_ok()))
+            .unwrap_or(ColumnType::String)
+    };
+
+    ColumnSpec {
+        name: name.to_string(),
+        column_type,
+        nullable,
+    }
+}
+
+/// A decimal is an optional sign, digits and an optional fractional part: `-12.50`, `3`, `.5`.
+fn check_decimal(value: &str, precision: Option<usize>, scale: Option<usize>) -> Result<(), String> {
+    let unsigned = value.strip_prefix(['-', '+']).unwrap_or(value);
+    let (int_part, frac_part) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if (int_part.is_empty() && frac_part.is_empty()) || !is_digits(int_part) || !is_digits(frac_part) {
+        return Err("not a decimal".to_string());
+    }
+
+    if let Some(scale) = scale
+        && frac_part.len() > scale
+    {
+        return Err(format!("more than {} decimals", scale));
+    }
+    if let Some(precision) = precision {
+        // like SQL's DECIMAL(precision, scale): the scale digits are reserved for the fraction
+        let int_digits = int_part.trim_start_matches('0').len();
+        if int_digits + frac_part.len() > precision
+            || int_digits > precision.saturating_sub(scale.unwrap_or(0))
+        {
+            return Err(format!("more than {} digits", precision));
+        }
+    }
+
+    Ok(())
+}
+
+/// How strictly the header must match the expected column list.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderMode {
+    /// Same names, same order.
+    #[default]
+    Exact,
+    /// Same order, names compared case-insensitively.
+    CaseInsensitive,
+    /// Same names, in any order.
+    AnyOrder,
+    /// All expected names in the expected order; extra columns are allowed anywhere.
+    AllowExtra,
+}
+
+/// A column name as compared: without leading and trailing whitespace, which header rows often have.
+fn column_name(name: &str) -> &str {
+    name.trim()
+}
+
+/// Compare a header with the expected column list; names are compared without leading and trailing whitespace.
+/// Returns one message per missing, unexpected or reordered column.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::validators::column_validators::{check_header, HeaderMode};
+///
+/// let header = ["id", "Name", "email"].map(String::from);
+/// let expected = ["id", "name"].map(String::from);
+///
+/// assert_eq!(check_header(&header, &expected, HeaderMode::Exact).len(), 3);
+/// assert_eq!(check_header(&header, &expected, HeaderMode::CaseInsensitive).len(), 1);
+/// ```
+pub fn check_header(header: &[String], expected: &[String], mode: HeaderMode) -> Vec<String> {
+    let normalize = |name: &String| match mode {
+        HeaderMode::CaseInsensitive => column_name(name).to_lowercase(),
+        _ => column_name(name).to_string(),
+    };
+    let actual: Vec<String> = header.iter().map(normalize).collect();
+    let wanted: Vec<String> = expected.iter().map(normalize).collect();
+
+    let mut messages = Vec::new();
+
+    for (i, name) in wanted.iter().enumerate() {
+        if !actual.contains(name) {
+            messages.push(format!("Missing column '{}' (expected at position {})", expected[i], i + 1));
+        }
+    }
+
+    if mode != HeaderMode::AllowExtra {
+        for (i, name) in actual.iter().enumerate() {
+            if !wanted.contains(name) {
+                messages.push(format!("Unexpected column '{}' at position {}", header[i], i + 1));
+            }
+        }
+    }
+
+    if mode != HeaderMode::AnyOrder {
+        // compare the relative order of the columns present in both lists
+        let found: Vec<usize> = actual
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| wanted.contains(name))
+            .map(|(i, _)| i)
+            .collect();
+        let in_order = wanted.iter().filter(|name| actual.contains(name));
+        for (&position, name) in found.iter().zip(in_order) {
+            if actual[position] != *name {
+                let expected_position = wanted.iter().position(|n| *n == actual[position]).unwrap_or(0);
+                messages.push(format!(
+                    "Column '{}' at position {}, expected at position {}",
+                    header[position],
+                    position + 1,
+                    expected_position + 1
+                ));
+            }
+        }
+    }
+
+    messages
+}
+
+/// Look up the position of each named column in the header, so column-aware validators can refer
+/// to columns by name rather than by position. Names are compared like in [`check_header`].
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::validators::column_validators::resolve_columns;
+///
+/// let header = ["id", "name", "email"].map(String::from);
+/// assert_eq!(resolve_columns(&header, ["email", "phone"]), vec![Some(2), None]);
+/// ```
+pub fn resolve_columns<'a>(
+    header: &[String],
+    names: impl IntoIterator<Item = &'a str>,
+) -> Vec<Option<usize>> {
+    let positions: HashMap<&str, usize> = header
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (column_name(name), i))
+        .collect();
+    names
+        .into_iter()
+        .map(|name| positions.get(column_name(name)).copied())
+        .collect()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ColumnTypesConfig {
+    /// Defaults to the top-level schema of the config file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Schema>,
+    pub common: CommonConfig,
+    #[serde(flatten)]
+    pub policy: Policy,
+}
+
+/// Checks every field against the type of its column in the schema.
+/// Columns are matched by position, or by name once a header is bound; schema columns that aren't
+/// in the header are reported once the input has been read.
+#[derive(Clone)]
+pub struct ColumnTypes {
+    cfg: ColumnTypesConfig,
+    columns: Vec<ColumnSpec>,
+    positions: Vec<Option<usize>>,
+}
+
+impl ColumnTypes {
+    pub fn new(cfg: ColumnTypesConfig) -> Self {
+        let columns = cfg.schema.as_ref().map(|schema| schema.columns.clone()).unwrap_or_default();
+        let positions = (0..columns.len()).map(Some).collect();
+        Self { cfg, columns, positions }
+    }
+}
+
+impl Validator for ColumnTypes {
+    fn name(&self) -> &'static str {
+        "column_types"
+    }
+
+    fn policy(&self) -> Policy {
+        self.cfg.policy
+    }
+
+    fn bind_dialect(&mut self, common: &CommonConfig) {
+        self.cfg.common.inherit(common);
+    }
+
+    fn validate<'a>(&self, input: &'a str, fields: &Fields<'a>, row: usize) -> ValidationResult<'a> {
+        let mut issues = Vec::new();
+
+        if !(self.cfg.common.has_header() && row == 1) {
+            let sep = self.cfg.common.separator.as_deref().unwrap_or(",");
+            let fields = fields.split_as(sep, Some(self.cfg.common.quote_char()));
+            for (column, position) in self.columns.iter().zip(&self.positions) {
+                let Some((position, value)) = position.and_then(|p| Some((p, fields.get(p)?))) else {
+                    continue;
+                };
+                if let Err(reason) = column.check(&value) {
+                    issues.push(ValidationIssue {
+                        column: Some(position),
+                        column_name: Some(column.name.clone()),
+                        original: Some(value.to_string()),
+                        ..ValidationIssue::new(
+                            self.name(),
+                            row,
+                            format!(
+                                "Invalid value on row {} in column '{}': |-> {} <-| ({})",
+                                row, column.name, value, reason
+                            ),
+                        )
+                    });
+                }
+            }
+        }
+
+        ValidationResult {
+            issues,
+            ..ValidationResult::new(input)
+        }
+    }
+
+    fn finalize(&self, _acc: Option<Box<dyn Accumulator>>) -> Vec<ValidationIssue> {
+        self.columns
+            .iter()
+            .zip(&self.positions)
+            .filter(|(_, position)| position.is_none())
+            .map(|(column, _)| ValidationIssue {
+                column_name: Some(column.name.clone()),
+                ..ValidationIssue::new(self.name(), 0, format!("Schema column '{}' not found", column.name))
+            })
+            .collect()
+    }
+
+    fn bind_header(&mut self, header: &[String]) {
+        let names = self.columns.iter().map(|c| c.name.as_str());
+        self.positions = resolve_columns(header, names);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HeaderConfig {
+    pub expected: Vec<String>,
+    #[serde(default)]
+    pub mode: HeaderMode,
+    pub common: CommonConfig,
+    #[serde(flatten)]
+    pub policy: Policy,
+}
+
+/// Compares the first row with the expected column list.
+#[derive(Clone)]
+pub struct Header {
+    cfg: HeaderConfig,
+}
+
+impl Header {
+    pub fn new(cfg: HeaderConfig) -> Self {
+        Self { cfg }
+    }
+}
+
+impl Validator for Header {
+    fn name(&self) -> &'static str {
+        "header"
+    }
+
+    fn policy(&self) -> Policy {
+        self.cfg.policy
+    }
+
+    fn bind_dialect(&mut self, common: &CommonConfig) {
+        self.cfg.common.inherit(common);
+    }
+
+    fn validate<'a>(&self, input: &'a str, _fields: &Fields<'a>, row: usize) -> ValidationResult<'a> {
+        let mut issues = Vec::new();
+
+        if row == 1 {
+            let sep = self.cfg.common.separator.as_deref().unwrap_or(",");
+            match line_processor(input, sep, Some(self.cfg.common.quote_char())) {
+                Ok(header) => {
+                    for message in check_header(&header, &self.cfg.expected, self.cfg.mode) {
+                        let message = format!("Invalid header: {}", message);
+                        issues.push(ValidationIssue::new(self.name(), row, message));
+                    }
+                }
+                Err(e) => {
+                    let message = format!("Failed to parse header: {}", e);
+                    issues.push(ValidationIssue::new(self.name(), row, message));
+                }
+            }
+        }
+
+        ValidationResult {
+            issues,
+            ..ValidationResult::new(input)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_types() {
+        assert!(ColumnType::Integer.check("-42").is_ok());
+        assert!(ColumnType::Integer.check("4.2").is_err());
+        assert!(ColumnType::Integer.check("").is_err());
+
+        let decimal = ColumnType::Decimal { precision: Some(5), scale: Some(2) };
+        assert!(decimal.check("123.45").is_ok());
+        assert!(decimal.check("0123.4").is_ok());
+        assert!(decimal.check("-.5").is_ok());
+        assert!(decimal.check("1234.5").is_err());
+        assert!(decimal.check("1.234").is_err());
+        assert!(decimal.check("1,5").is_err());
+        assert!(decimal.check(".").is_err());
+
+        let boolean = ColumnType::Boolean { truthy: default_truthy(), falsy: default_falsy() };
+        assert!(boolean.check("TRUE").is_ok());
+        assert!(boolean.check("n").is_ok());
+        assert!(boolean.check("maybe").is_err());
+
+        let date = ColumnType::Date { format: "%d/%m/%Y".to_string() };
+        assert!(date.check("31/12/2020").is_ok());
+        assert!(date.check("2020-12-31").is_err());
+
+        let datetime = ColumnType::DateTime { format: default_datetime_format() };
+        assert!(datetime.check("2020-12-31 23:59:59").is_ok());
+        assert!(datetime.check("2020-12-31").is_err());
+    }
+
+    #[test]
+    fn test_nullable() {
+        let column = ColumnSpec {
+            name: "int_3".to_string(),
+            column_type: ColumnType::Integer,
+            nullable: true,
+        };
+        assert!(column.check("").is_ok());
+
+        let column = ColumnSpec { nullable: false, ..column };
+        assert_eq!(column.check("").unwrap_err(), "not an integer");
+    }
+
+    #[test]
+    fn test_check_header() {
+        let expected = ["id", "name", "email"].map(String::from);
+
+        let header = ["id", "name", "email"].map(String::from);
+        for mode in [HeaderMode::Exact, HeaderMode::CaseInsensitive, HeaderMode::AnyOrder, HeaderMode::AllowExtra] {
+            assert!(check_header(&header, &expected, mode).is_empty());
+        }
+
+        let header = ["id", "email", "name"].map(String::from);
+        let messages = check_header(&header, &expected, HeaderMode::Exact);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0], "Column 'email' at position 2, expected at position 3");
+        assert!(check_header(&header, &expected, HeaderMode::AnyOrder).is_empty());
+
+        let header = ["ID", "extra", "name", "email"].map(String::from);
+        assert_eq!(
+            check_header(&header, &expected, HeaderMode::CaseInsensitive),
+            vec!["Unexpected column 'extra' at position 2"]
+        );
+        let messages = check_header(&header, &expected, HeaderMode::AllowExtra);
+        assert_eq!(messages, vec!["Missing column 'id' (expected at position 1)"]);
+
+        let header = ["id", "name"].map(String::from);
+        assert_eq!(
+            check_header(&header, &expected, HeaderMode::AnyOrder),
+            vec!["Missing column 'email' (expected at position 3)"]
+        );
+
+        // whitespace around names doesn't count, like when columns are looked up by name
+        let header = [" id", "name ", "email"].map(String::from);
+        assert!(check_header(&header, &expected, HeaderMode::Exact).is_empty());
+        assert_eq!(resolve_columns(&header, ["id", " name"]), [Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn test_column_types_bind_header() {
+        let schema: Schema = serde_yaml::from_str("columns: [{name: id, type: integer}]").unwrap();
+        let mut validator = ColumnTypes::new(ColumnTypesConfig {
+            schema: Some(schema),
+            common: CommonConfig {
+                separator: Some(";".to_string()),
+                has_header: Some(true),
+                ..CommonConfig::default()
+            },
+            policy: Policy::default(),
+        });
+
+        let validate = |validator: &ColumnTypes, row: &str, n| {
+            validator.validate(row, &Fields::new(row, ";", Some('"')), n).issues
+        };
+
+        // the header row itself is skipped
+        assert!(validate(&validator, "name;id", 1).is_empty());
+
+        validator.bind_header(&["name".to_string(), "id".to_string()]);
+        assert!(validate(&validator, "x;1", 2).is_empty());
+        let issues = validate(&validator, "1;x", 3);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].column, Some(1));
+        assert_eq!(issues[0].column_name.as_deref(), Some("id"));
+        assert!(validator.finalize(None).is_empty());
+
+        // a schema column that isn't in the header is reported, not skipped silently
+        validator.bind_header(&["name".to_string(), "ident".to_string()]);
+        let issues = validator.finalize(None);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].message, "Schema column 'id' not found");
+    }
+}
@@ -0,0 +1,908 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::CommonConfig;
+use crate::utils::temp_file::TempFile;
+use crate::utils::tokenizer::Fields;
+use crate::validators::column_validators::resolve_columns;
+use crate::validators::issue::{ErrorPolicy, Policy, ValidationIssue, ValidationResult};
+use crate::validators::{Accumulator, RowFilter, Validator, downcast_accumulator};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LineCountConfig {
+    pub min: Option<usize>,
+    pub max: Option<usize>,
+    pub common: CommonConfig,
+    #[serde(flatten)]
+    pub policy: Policy,
+}
+
+/// File-level validator: counts the data records (the header excluded) and checks them against
+/// `[min, max]` once the whole file has been read.
+#[derive(Clone)]
+pub struct LineCount {
+    cfg: LineCountConfig,
+}
+
+impl LineCount {
+    pub fn new(cfg: LineCountConfig) -> Self {
+        Self { cfg }
+    }
+}
+
+struct RecordCounter {
+    count: usize,
+    skip_header: bool,
+}
+
+impl Accumulator for RecordCounter {
+    fn accumulate(&mut self, _input: &str, _fields: &Fields, row: usize) {
+        if !(self.skip_header && row == 1) {
+            self.count += 1;
+        }
+    }
+
+    fn merge(&mut self, other: Box<dyn Accumulator>) {
+        self.count += downcast_accumulator::<RecordCounter>(other).count;
+    }
+}
+
+impl Validator for LineCount {
+    fn name(&self) -> &'static str {
+        "line_count"
+    }
+
+    fn policy(&self) -> Policy {
+        self.cfg.policy
+    }
+
+    fn bind_dialect(&mut self, common: &CommonConfig) {
+        self.cfg.common.inherit(common);
+    }
+
+    fn validate<'a>(&self, input: &'a str, _fields: &Fields<'a>, _row: usize) -> ValidationResult<'a> {
+        ValidationResult::new(input)
+    }
+
+    fn accumulator(&self) -> Option<Box<dyn Accumulator>> {
+        Some(Box::new(RecordCounter {
+            count: 0,
+            skip_header: self.cfg.common.has_header(),
+        }))
+    }
+
+    fn finalize(&self, acc: Option<Box<dyn Accumulator>>) -> Vec<ValidationIssue> {
+        let count = acc.map_or(0, |acc| downcast_accumulator::<RecordCounter>(acc).count);
+        let message = match (self.cfg.min, self.cfg.max) {
+            (Some(min), _) if count < min => {
+                Some(format!("Expected at least {} records, found {}", min, count))
+            }
+            (_, Some(max)) if count > max => {
+                Some(format!("Expected at most {} records, found {}", max, count))
+            }
+            _ => None,
+        };
+        // file-level issues aren't tied to a row
+        message
+            .map(|message| ValidationIssue::new(self.name(), 0, message))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Key columns, by name once a header is bound, or else by position (1-based).
+#[derive(Debug, Clone)]
+pub(crate) struct KeyColumns {
+    names: Vec<String>,
+    positions: Vec<Option<usize>>,
+}
+
+impl KeyColumns {
+    pub(crate) fn new(names: &[String]) -> Self {
+        let positions = names
+            .iter()
+            .map(|name| name.parse::<usize>().ok().and_then(|position| position.checked_sub(1)))
+            .collect();
+        Self { names: names.to_vec(), positions }
+    }
+
+    pub(crate) fn bind_header(&mut self, header: &[String]) {
+        self.positions = resolve_columns(header, self.names.iter().map(String::as_str));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The positions of the columns, if all of them are found.
+    pub(crate) fn positions(&self) -> Option<Vec<usize>> {
+        self.positions.iter().copied().collect()
+    }
+
+    /// The columns that aren't found.
+    pub(crate) fn missing(&self) -> impl Iterator<Item = &str> {
+        self.names
+            .iter()
+            .zip(&self.positions)
+            .filter(|(_, position)| position.is_none())
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Joins the fields of a composite key.
+const KEY_SEPARATOR: char = '\u{1f}';
+
+/// The key of a record: its fields at `positions`, or `None` for a record without all of them.
+pub(crate) fn record_key(fields: &Fields, positions: &[usize]) -> Option<String> {
+    let mut key = String::new();
+    for (i, &position) in positions.iter().enumerate() {
+        if i > 0 {
+            key.push(KEY_SEPARATOR);
+        }
+        key.push_str(&fields.get(position)?);
+    }
+    Some(key)
+}
+
+/// A key as shown in messages, with the fields of a composite key separated by commas.
+pub(crate) fn display_key(key: &str) -> String {
+    key.split(KEY_SEPARATOR).collect::<Vec<_>>().join(", ")
+}
+
+/// Where the keys of the `unique` validator are kept.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UniqueMode {
+    /// In a hash map, all of them.
+    #[default]
+    Exact,
+    /// In a hash map up to the memory limit, and in sorted runs on disk beyond it.
+    Spill,
+}
+
+impl FromStr for UniqueMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "exact" => Ok(UniqueMode::Exact),
+            "spill" => Ok(UniqueMode::Spill),
+            _ => Err(format!("Unknown unique mode: '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UniqueConfig {
+    /// Key columns: names, or 1-based positions without a header.
+    pub columns: Vec<String>,
+    #[serde(default)]
+    pub mode: UniqueMode,
+    pub common: CommonConfig,
+    #[serde(flatten)]
+    pub policy: Policy,
+}
+
+/// File-level validator: the key of every record (the values of one or more columns) may only be
+/// found once. Every duplicated key is reported once the whole file has been read, with all the rows it is on.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::config::CommonConfig;
+/// use csv_validator_core::utils::tokenizer::Fields;
+/// use csv_validator_core::validators::Validator;
+/// use csv_validator_core::validators::file_validators::{Unique, UniqueConfig, UniqueMode};
+/// use csv_validator_core::validators::issue::Policy;
+///
+/// let validator = Unique::new(UniqueConfig {
+///     columns: vec!["1".to_string(), "2".to_string()],
+///     mode: UniqueMode::Exact,
+///     common: CommonConfig { separator: Some(";".to_string()), ..CommonConfig::default() },
+///     policy: Policy::default(),
+/// });
+///
+/// let mut acc = validator.accumulator().unwrap();
+/// for (row, line) in ["1;a;x", "1;b;y", "1;a;z", "1;a;x"].iter().enumerate() {
+///     acc.accumulate(line, &Fields::new(line, ";", Some('"')), row + 1);
+/// }
+///
+/// let issues = validator.finalize(Some(acc));
+/// assert_eq!(issues[0].line_number, 3);
+/// assert_eq!(issues[0].message, "Duplicate key 1, a on rows 1, 3, 4");
+/// ```
+#[derive(Clone)]
+pub struct Unique {
+    cfg: UniqueConfig,
+    columns: KeyColumns,
+    mem_limit: usize,
+}
+
+impl Unique {
+    pub fn new(cfg: UniqueConfig) -> Self {
+        let columns = KeyColumns::new(&cfg.columns);
+        Self { cfg, columns, mem_limit: usize::MAX }
+    }
+}
+
+/// Estimated memory taken by a key in the hash map, besides its bytes and rows.
+const KEY_OVERHEAD: usize = 64;
+
+/// Where a key is found: a row, of a partition in dataset mode (and of partition 0 otherwise).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct KeyRow {
+    pub(crate) partition: usize,
+    pub(crate) row: usize,
+}
+
+/// The keys of a file, with the rows they are on. In spill mode, the keys are written to a run on disk,
+/// sorted, whenever they take more than the memory limit; the runs are merged, [`MERGE_WIDTH`] at a time,
+/// once the file has been read.
+pub(crate) struct KeyStore {
+    separator: String,
+    quote_char: char,
+    skip_header: bool,
+    /// Empty when the key columns aren't found.
+    positions: Vec<usize>,
+    partition: usize,
+    /// Keep only the first row of a key in every partition.
+    first_only: bool,
+    spill: bool,
+    mem_limit: usize,
+    keys: HashMap<String, Vec<KeyRow>>,
+    bytes: usize,
+    runs: Vec<TempFile>,
+    /// Why the keys couldn't be spilled; no more keys are collected then.
+    error: Option<String>,
+}
+
+impl KeyStore {
+    /// A store for the keys at `positions` of the records read with `common`; empty `positions` collect nothing.
+    pub(crate) fn new(common: &CommonConfig, positions: Vec<usize>, spill: bool, mem_limit: usize) -> Self {
+        Self {
+            separator: common.separator.clone().unwrap_or_else(|| ",".to_string()),
+            quote_char: common.quote_char(),
+            skip_header: common.has_header(),
+            positions,
+            partition: 0,
+            first_only: false,
+            spill,
+            mem_limit,
+            keys: HashMap::new(),
+            bytes: 0,
+            runs: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Tag the keys with partition `index`, and keep only the row a key is first found on in the partition.
+    pub(crate) fn partition(mut self, index: usize) -> Self {
+        self.partition = index;
+        self.first_only = true;
+        self
+    }
+
+    /// Add the key of a record, if it has one.
+    pub(crate) fn add(&mut self, fields: &Fields, row: usize) {
+        if self.positions.is_empty() {
+            return;
+        }
+        // short rows have no key; the field count check reports them
+        if let Some(key) = record_key(fields, &self.positions) {
+            let partition = self.partition;
+            self.insert(key, [KeyRow { partition, row }]);
+            self.spill_if_full();
+        }
+    }
+
+    /// Add a key found on a row, not made of fields, like a hash of the record.
+    pub(crate) fn add_key(&mut self, key: String, row: usize) {
+        if self.error.is_none() {
+            let partition = self.partition;
+            self.insert(key, [KeyRow { partition, row }]);
+            self.spill_if_full();
+        }
+    }
+
+    /// Add the keys of another store, in memory or spilled.
+    pub(crate) fn append(&mut self, other: KeyStore) {
+        for (key, rows) in other.keys {
+            self.insert(key, rows);
+        }
+        self.runs.extend(other.runs);
+        if self.error.is_none() {
+            self.error = other.error;
+        }
+        self.spill_if_full();
+    }
+
+    fn insert(&mut self, key: String, rows: impl IntoIterator<Item = KeyRow>) {
+        let before = self.keys.len();
+        let first_only = self.first_only;
+        let entry = self.keys.entry(key).or_default();
+        let len = entry.len();
+        for row in rows {
+            match entry.iter_mut().find(|other| first_only && other.partition == row.partition) {
+                Some(other) => other.row = other.row.min(row.row),
+                None => entry.push(row),
+            }
+        }
+        self.bytes += (entry.len() - len) * size_of::<KeyRow>();
+        if self.keys.len() > before {
+            self.bytes += KEY_OVERHEAD;
+        }
+    }
+
+    fn spill_if_full(&mut self) {
+        if self.spill
+            && self.bytes > self.mem_limit
+            && self.error.is_none()
+            && let Err(e) = self.write_run()
+        {
+            self.error = Some(e.to_string());
+            self.positions.clear();
+            self.keys.clear();
+        }
+    }
+
+    /// Write the keys in memory to a new run, sorted by key.
+    fn write_run(&mut self) -> io::Result<()> {
+        let mut keys: Vec<(String, Vec<KeyRow>)> = self.keys.drain().collect();
+        keys.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        self.bytes = 0;
+
+        let (run, file) = TempFile::create("keys", "bin")?;
+        let mut writer = BufWriter::new(file);
+        for (key, rows) in keys {
+            write_key(&mut writer, &key, &rows)?;
+        }
+        writer.flush()?;
+        self.runs.push(run);
+        Ok(())
+    }
+
+    /// The keys found more than once, with their rows.
+    pub(crate) fn duplicates(mut self) -> io::Result<Vec<(String, Vec<KeyRow>)>> {
+        if let Some(error) = self.error {
+            return Err(io::Error::other(error));
+        }
+        if self.runs.is_empty() {
+            return Ok(self.keys.into_iter().filter(|(_, rows)| rows.len() > 1).collect());
+        }
+        self.write_run()?;
+
+        // with more runs than can be merged at once, merge the oldest into one run until there are few enough
+        while self.runs.len() > MERGE_WIDTH {
+            let runs: Vec<TempFile> = self.runs.drain(..MERGE_WIDTH).collect();
+            let (run, file) = TempFile::create("keys", "bin")?;
+            let mut writer = BufWriter::new(file);
+            merge_runs(&runs, |key, rows| write_key(&mut writer, &key, &rows))?;
+            writer.flush()?;
+            self.runs.push(run);
+        }
+
+        let mut duplicates = Vec::new();
+        merge_runs(&self.runs, |key, rows| {
+            if rows.len() > 1 {
+                duplicates.push((key, rows));
+            }
+            Ok(())
+        })?;
+        Ok(duplicates)
+    }
+}
+
+/// The most runs merged at once: every one of them has a file open and a key in memory.
+const MERGE_WIDTH: usize = 16;
+
+/// Merge sorted runs: `each` is called for every key, in order, with its rows from all the runs.
+fn merge_runs(
+    runs: &[TempFile],
+    mut each: impl FnMut(String, Vec<KeyRow>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut readers = runs
+        .iter()
+        .map(|run| Ok(BufReader::new(File::open(run.path())?)))
+        .collect::<io::Result<Vec<_>>>()?;
+    // the smallest key of every run is on the heap
+    let mut heap = BinaryHeap::new();
+    for (i, reader) in readers.iter_mut().enumerate() {
+        if let Some((key, rows)) = read_key(reader)? {
+            heap.push(Reverse((key, i, rows)));
+        }
+    }
+
+    while let Some(Reverse((key, i, mut rows))) = heap.pop() {
+        if let Some((next, next_rows)) = read_key(&mut readers[i])? {
+            heap.push(Reverse((next, i, next_rows)));
+        }
+        while heap.peek().is_some_and(|Reverse((next, ..))| *next == key) {
+            let Some(Reverse((_, j, more))) = heap.pop() else { break };
+            rows.extend(more);
+            if let Some((next, next_rows)) = read_key(&mut readers[j])? {
+                heap.push(Reverse((next, j, next_rows)));
+            }
+        }
+        each(key, rows)?;
+    }
+    Ok(())
+}
+
+/// Write a key to a run: its length, its bytes, the number of rows and the rows, with their partitions.
+fn write_key(writer: &mut impl Write, key: &str, rows: &[KeyRow]) -> io::Result<()> {
+    writer.write_all(&(key.len() as u64).to_le_bytes())?;
+    writer.write_all(key.as_bytes())?;
+    writer.write_all(&(rows.len() as u64).to_le_bytes())?;
+    for row in rows {
+        writer.write_all(&(row.partition as u64).to_le_bytes())?;
+        writer.write_all(&(row.row as u64).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Read the next key of a run, if there is one.
+fn read_key(reader: &mut impl Read) -> io::Result<Option<(String, Vec<KeyRow>)>> {
+    let mut buf = [0; 8];
+    match reader.read_exact(&mut buf) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut key = vec![0; u64::from_le_bytes(buf) as usize];
+    reader.read_exact(&mut key)?;
+    let key = String::from_utf8(key).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    reader.read_exact(&mut buf)?;
+    let count = u64::from_le_bytes(buf) as usize;
+    let mut rows = Vec::with_capacity(count);
+    for _ in 0..count {
+        reader.read_exact(&mut buf)?;
+        let partition = u64::from_le_bytes(buf) as usize;
+        reader.read_exact(&mut buf)?;
+        rows.push(KeyRow { partition, row: u64::from_le_bytes(buf) as usize });
+    }
+    Ok(Some((key, rows)))
+}
+
+impl Accumulator for KeyStore {
+    fn accumulate(&mut self, _input: &str, fields: &Fields, row: usize) {
+        if self.positions.is_empty() || (self.skip_header && row == 1) {
+            return;
+        }
+        // short rows have no key; the field count check reports them
+        let key = record_key(&fields.split_as(&self.separator, Some(self.quote_char)), &self.positions);
+        if let Some(key) = key {
+            self.add_key(key, row);
+        }
+    }
+
+    fn merge(&mut self, other: Box<dyn Accumulator>) {
+        self.append(*downcast_accumulator::<KeyStore>(other));
+    }
+}
+
+impl Validator for Unique {
+    fn name(&self) -> &'static str {
+        "unique"
+    }
+
+    fn policy(&self) -> Policy {
+        self.cfg.policy
+    }
+
+    fn bind_dialect(&mut self, common: &CommonConfig) {
+        self.cfg.common.inherit(common);
+    }
+
+    fn validate<'a>(&self, input: &'a str, _fields: &Fields<'a>, _row: usize) -> ValidationResult<'a> {
+        ValidationResult::new(input)
+    }
+
+    fn accumulator(&self) -> Option<Box<dyn Accumulator>> {
+        let positions = self.columns.positions().unwrap_or_default();
+        let spill = self.cfg.mode == UniqueMode::Spill;
+        Some(Box::new(KeyStore::new(&self.cfg.common, positions, spill, self.mem_limit)))
+    }
+
+    fn finalize(&self, acc: Option<Box<dyn Accumulator>>) -> Vec<ValidationIssue> {
+        let missing: Vec<ValidationIssue> = self
+            .columns
+            .missing()
+            .map(|column| ValidationIssue::new(self.name(), 0, format!("Key column '{}' not found", column)))
+            .collect();
+        let Some(store) = acc.map(downcast_accumulator::<KeyStore>) else {
+            return missing;
+        };
+        if !missing.is_empty() {
+            return missing;
+        }
+
+        let mut duplicates = match store.duplicates() {
+            Ok(duplicates) => duplicates,
+            Err(error) => {
+                let message = format!("Keys could not be checked for duplicates: {}", error);
+                return vec![ValidationIssue::new(self.name(), 0, message)];
+            }
+        };
+
+        // issues are on the first repeated row
+        for (_, rows) in duplicates.iter_mut() {
+            rows.sort_unstable();
+        }
+        duplicates.sort_unstable_by_key(|(_, rows)| rows[1]);
+        let column = self.columns.positions().and_then(|positions| positions.first().copied());
+        duplicates
+            .into_iter()
+            .map(|(key, rows)| {
+                let key = display_key(&key);
+                let list = rows.iter().map(|row| row.row.to_string()).collect::<Vec<_>>().join(", ");
+                ValidationIssue {
+                    column,
+                    column_name: (self.cfg.common.has_header()).then(|| self.cfg.columns[0].clone()),
+                    original: Some(key.clone()),
+                    ..ValidationIssue::new(self.name(), rows[1].row, format!("Duplicate key {} on rows {}", key, list))
+                }
+            })
+            .collect()
+    }
+
+    fn uses_memory(&self) -> bool {
+        self.cfg.mode == UniqueMode::Spill
+    }
+
+    fn bind_mem_limit(&mut self, mem_limit_bytes: usize) {
+        self.mem_limit = mem_limit_bytes;
+    }
+
+    fn bind_header(&mut self, header: &[String]) {
+        self.columns.bind_header(header);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DuplicateRowsConfig {
+    /// Compare fields without leading and trailing whitespace.
+    #[serde(default)]
+    pub trim: bool,
+    /// Compare fields case-insensitively.
+    #[serde(default)]
+    pub ignore_case: bool,
+    pub common: CommonConfig,
+    #[serde(flatten)]
+    pub policy: Policy,
+}
+
+/// Validator: report every record that is the same as one before it, field by field (unquoted, and
+/// optionally trimmed and case folded). Only a 128-bit hash of every record is kept, not the records.
+/// As many hashes as fit in half the memory limit are kept in memory, and the records found there are
+/// reported as they are read: with the `fix` policy, they are left out of the output. The hashes of the
+/// records that don't fit are spilled to disk, sorted, and their duplicates are reported once the file has
+/// been read, when these records have already been written.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::config::CommonConfig;
+/// use csv_validator_core::utils::tokenizer::Fields;
+/// use csv_validator_core::validators::Validator;
+/// use csv_validator_core::validators::file_validators::{DuplicateRows, DuplicateRowsConfig};
+/// use csv_validator_core::validators::issue::{ErrorPolicy, Policy};
+///
+/// let validator = DuplicateRows::new(DuplicateRowsConfig {
+///     trim: true,
+///     ignore_case: true,
+///     common: CommonConfig::default(),
+///     policy: Policy { on_error: ErrorPolicy::Fix, ..Policy::default() },
+/// });
+///
+/// // the rows are hashed on the worker threads, and compared in input order
+/// let mut filter = validator.row_filter().unwrap();
+/// let mut check = |line: &str, row| {
+///     let digest = validator.row_digest(line, &Fields::new(line, ",", Some('"')));
+///     filter.check(line, row, digest)
+/// };
+/// assert!(check("1,Anna", 1).is_empty());
+/// assert!(check("2,Bob", 2).is_empty());
+///
+/// let issues = check("1,\"ANNA \"", 3);
+/// assert_eq!(issues[0].message, "Row 3 is a duplicate of row 1");
+/// assert!(issues[0].fixed);
+/// ```
+#[derive(Clone)]
+pub struct DuplicateRows {
+    cfg: DuplicateRowsConfig,
+    hasher: RowHasher,
+    mem_limit: usize,
+}
+
+impl DuplicateRows {
+    pub fn new(cfg: DuplicateRowsConfig) -> Self {
+        let hasher = RowHasher::new(&cfg);
+        Self {
+            cfg,
+            hasher,
+            mem_limit: usize::MAX,
+        }
+    }
+}
+
+/// Hashes records by their fields, normalized the way [`DuplicateRows`] compares them.
+#[derive(Clone)]
+struct RowHasher {
+    separator: String,
+    quote_char: char,
+    trim: bool,
+    ignore_case: bool,
+}
+
+impl RowHasher {
+    fn new(cfg: &DuplicateRowsConfig) -> Self {
+        Self {
+            separator: cfg.common.separator.clone().unwrap_or_else(|| ",".to_string()),
+            quote_char: cfg.common.quote_char(),
+            trim: cfg.trim,
+            ignore_case: cfg.ignore_case,
+        }
+    }
+
+    /// Two 64-bit hashes of the normalized fields, for collisions that are unlikely even in billions of rows.
+    fn hash(&self, fields: &Fields) -> u128 {
+        let (mut first, mut second) = (DefaultHasher::new(), DefaultHasher::new());
+        second.write_u8(1);
+        for field in fields.split_as(&self.separator, Some(self.quote_char)).iter() {
+            let field = if self.trim { field.trim() } else { &field };
+            let field = if self.ignore_case { Cow::Owned(field.to_lowercase()) } else { Cow::Borrowed(field) };
+            for hasher in [&mut first, &mut second] {
+                hasher.write(field.as_bytes());
+                // never found in UTF-8, so fields can't run into each other
+                hasher.write_u8(0xff);
+            }
+        }
+        (u128::from(first.finish()) << 64) | u128::from(second.finish())
+    }
+}
+
+/// Estimated memory taken by a row in the set of rows seen: its hash, its row and the hash map overhead.
+const SEEN_ROW_SIZE: usize = 40;
+
+/// The hashes of the records seen, with the row they were first seen on: in memory as long as they fit in
+/// half the memory limit, and spilled to disk, with the other half, from then on.
+struct SeenRows {
+    hasher: RowHasher,
+    skip_header: bool,
+    fix: bool,
+    mem_limit: usize,
+    seen: HashMap<u128, usize>,
+    /// The hashes not in memory, by their hex digits; a record found here can't be in `seen`.
+    spilled: KeyStore,
+}
+
+impl RowFilter for SeenRows {
+    fn check(&mut self, input: &str, row: usize, digest: Option<u128>) -> Vec<ValidationIssue> {
+        if self.skip_header && row == 1 {
+            return Vec::new();
+        }
+        let hasher = &self.hasher;
+        let hash = digest.unwrap_or_else(|| hasher.hash(&Fields::new(input, &hasher.separator, Some(hasher.quote_char))));
+        let full = (self.seen.len() + 1).saturating_mul(SEEN_ROW_SIZE) > self.mem_limit;
+        match self.seen.entry(hash) {
+            Entry::Vacant(_) if full => {
+                self.spilled.add_key(format!("{:032x}", hash), row);
+                Vec::new()
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(row);
+                Vec::new()
+            }
+            Entry::Occupied(entry) => {
+                let message = format!("Row {} is a duplicate of row {}", row, entry.get());
+                vec![ValidationIssue { fixed: self.fix, ..ValidationIssue::new("duplicate_rows", row, message) }]
+            }
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Option<Box<dyn Accumulator>> {
+        Some(Box::new(self.spilled))
+    }
+}
+
+impl Validator for DuplicateRows {
+    fn name(&self) -> &'static str {
+        "duplicate_rows"
+    }
+
+    fn policy(&self) -> Policy {
+        self.cfg.policy
+    }
+
+    fn bind_dialect(&mut self, common: &CommonConfig) {
+        self.cfg.common.inherit(common);
+        self.hasher = RowHasher::new(&self.cfg);
+    }
+
+    fn validate<'a>(&self, input: &'a str, _fields: &Fields<'a>, _row: usize) -> ValidationResult<'a> {
+        ValidationResult::new(input)
+    }
+
+    fn row_filter(&self) -> Option<Box<dyn RowFilter>> {
+        Some(Box::new(SeenRows {
+            hasher: self.hasher.clone(),
+            skip_header: self.cfg.common.has_header(),
+            fix: self.should_fix(),
+            mem_limit: self.mem_limit / 2,
+            seen: HashMap::new(),
+            spilled: KeyStore::new(&self.cfg.common, Vec::new(), true, self.mem_limit / 2),
+        }))
+    }
+
+    fn row_digest(&self, _input: &str, fields: &Fields) -> Option<u128> {
+        Some(self.hasher.hash(fields))
+    }
+
+    fn finalize(&self, acc: Option<Box<dyn Accumulator>>) -> Vec<ValidationIssue> {
+        let Some(store) = acc.map(downcast_accumulator::<KeyStore>) else {
+            return Vec::new();
+        };
+        let duplicates = match store.duplicates() {
+            Ok(duplicates) => duplicates,
+            Err(error) => {
+                let message = format!("Rows could not be checked for duplicates: {}", error);
+                return vec![ValidationIssue::new(self.name(), 0, message)];
+            }
+        };
+
+        // the rows were written by now: they are reported, not left out
+        let mut issues: Vec<ValidationIssue> = duplicates
+            .into_iter()
+            .flat_map(|(_, mut rows)| {
+                rows.sort_unstable();
+                let first = rows[0].row;
+                rows.into_iter().skip(1).map(move |row| {
+                    let message = format!("Row {} is a duplicate of row {}", row.row, first);
+                    ValidationIssue::new(self.name(), row.row, message)
+                })
+            })
+            .collect();
+        issues.sort_unstable_by_key(|issue| issue.line_number);
+        issues
+    }
+
+    fn should_fix(&self) -> bool {
+        self.cfg.policy.on_error == ErrorPolicy::Fix
+    }
+
+    fn uses_memory(&self) -> bool {
+        true
+    }
+
+    fn bind_mem_limit(&mut self, mem_limit_bytes: usize) {
+        self.mem_limit = mem_limit_bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accumulate a row split the way the engine splits it.
+    fn accumulate(acc: &mut Box<dyn Accumulator>, line: &str, row: usize) {
+        acc.accumulate(line, &Fields::new(line, ",", Some('"')), row);
+    }
+
+    #[test]
+    fn test_line_count() {
+        let validator = LineCount::new(LineCountConfig {
+            min: Some(3),
+            max: None,
+            common: CommonConfig { has_header: Some(true), ..CommonConfig::default() },
+            policy: Policy::default(),
+        });
+
+        // two workers, merged
+        let mut acc = validator.accumulator().unwrap();
+        accumulate(&mut acc, "id", 1);
+        accumulate(&mut acc, "1", 2);
+        let mut other = validator.accumulator().unwrap();
+        accumulate(&mut other, "2", 3);
+        acc.merge(other);
+
+        let issues = validator.finalize(Some(acc));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line_number, 0);
+        assert_eq!(issues[0].message, "Expected at least 3 records, found 2");
+    }
+
+    fn unique(columns: &[&str], mode: UniqueMode, mem_limit: usize) -> Unique {
+        let mut validator = Unique::new(UniqueConfig {
+            columns: columns.iter().map(|column| column.to_string()).collect(),
+            mode,
+            common: CommonConfig { has_header: Some(true), ..CommonConfig::default() },
+            policy: Policy::default(),
+        });
+        validator.bind_header(&["id".to_string(), "day".to_string(), "amount".to_string()]);
+        validator.bind_mem_limit(mem_limit);
+        validator
+    }
+
+    /// Issues of the rows, split over two workers.
+    fn duplicates(validator: &Unique, rows: &[&str]) -> Vec<(usize, String)> {
+        let (first, second) = rows.split_at(rows.len() / 2);
+        let mut acc = validator.accumulator().unwrap();
+        for (row, line) in first.iter().enumerate() {
+            accumulate(&mut acc, line, row + 1);
+        }
+        let mut other = validator.accumulator().unwrap();
+        for (row, line) in second.iter().enumerate() {
+            accumulate(&mut other, line, first.len() + row + 1);
+        }
+        acc.merge(other);
+
+        validator.finalize(Some(acc)).into_iter().map(|issue| (issue.line_number, issue.message)).collect()
+    }
+
+    const ROWS: [&str; 9] = [
+        "id,day,amount",
+        "1,mon,10",
+        "2,mon,20",
+        "1,tue,30",
+        "1,mon,40",
+        "3,wed",
+        "2,mon,50",
+        "1,mon,60",
+        "\"1\",tue,70",
+    ];
+
+    #[test]
+    fn test_unique_composite_keys() {
+        let validator = unique(&["id", "day"], UniqueMode::Exact, usize::MAX);
+        assert_eq!(
+            duplicates(&validator, &ROWS),
+            [
+                (5, "Duplicate key 1, mon on rows 2, 5, 8".to_string()),
+                (7, "Duplicate key 2, mon on rows 3, 7".to_string()),
+                (9, "Duplicate key 1, tue on rows 4, 9".to_string()),
+            ]
+        );
+
+        let validator = unique(&["amount"], UniqueMode::Exact, usize::MAX);
+        assert!(duplicates(&validator, &ROWS).is_empty());
+
+        let validator = unique(&["id", "customer"], UniqueMode::Exact, usize::MAX);
+        assert_eq!(duplicates(&validator, &ROWS), [(0, "Key column 'customer' not found".to_string())]);
+    }
+
+    #[test]
+    fn test_unique_spill() {
+        let exact = duplicates(&unique(&["id", "day"], UniqueMode::Exact, 0), &ROWS);
+
+        // every record goes to a run of its own
+        let validator = unique(&["id", "day"], UniqueMode::Spill, 0);
+        let mut acc = validator.accumulator().unwrap();
+        for (row, line) in ROWS.iter().enumerate() {
+            accumulate(&mut acc, line, row + 1);
+        }
+        assert_eq!(downcast_accumulator::<KeyStore>(acc).runs.len(), ROWS.len() - 1);
+
+        assert_eq!(duplicates(&validator, &ROWS), exact);
+        assert_eq!(duplicates(&unique(&["id", "day"], UniqueMode::Spill, 200), &ROWS), exact);
+    }
+
+    #[test]
+    fn test_unique_merge_passes() {
+        // a run per record: far more runs than are merged at once, so they are merged in passes
+        let lines: Vec<String> = (0..MERGE_WIDTH * 20).map(|i| format!("{},mon,{}", i % 37, i)).collect();
+        let rows: Vec<&str> = ["id,day,amount"].into_iter().chain(lines.iter().map(String::as_str)).collect();
+
+        let exact = duplicates(&unique(&["id"], UniqueMode::Exact, usize::MAX), &rows);
+        assert_eq!(exact.len(), 37);
+        assert_eq!(duplicates(&unique(&["id"], UniqueMode::Spill, 0), &rows), exact);
+    }
+}
@@ -1,20 +1,140 @@
-#[derive(Debug, Clone, PartialEq)]
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    #[default]
+    Error,
+    Fatal,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Fatal => "fatal",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            "fatal" => Ok(Severity::Fatal),
+            _ => Err(format!("Unknown severity: '{}'", s)),
+        }
+    }
+}
+
+/// What to do with a row when a validator reports an issue it didn't fix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorPolicy {
+    /// Report the issue, keep the row.
+    Report,
+    /// Fix the row; for validators that have no fix, the same as `Report`.
+    Fix,
+    /// Report the issue and drop the row (to the rejects, if any).
+    #[default]
+    Drop,
+    /// Stop processing the file.
+    Abort,
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "report" => Ok(ErrorPolicy::Report),
+            "fix" => Ok(ErrorPolicy::Fix),
+            "drop" => Ok(ErrorPolicy::Drop),
+            "abort" => Ok(ErrorPolicy::Abort),
+            _ => Err(format!("Unknown error policy: '{}'", s)),
+        }
+    }
+}
+
+/// How the issues of a single validator are classified and handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Policy {
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(default)]
+    pub on_error: ErrorPolicy,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct ValidationIssue {
+    /// The file the issue was found in, when several files are validated together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Name of the validator that reported the issue.
+    pub validator: String,
+    /// The row (1-based, counting the header), or 0 for file-level issues.
     pub line_number: usize,
+    /// The physical lines (1-based) the row was read from: a quoted field may span several lines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_line: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<usize>,
+    /// Column index (0-based), for issues about a single field.
+    pub column: Option<usize>,
+    pub column_name: Option<String>,
+    /// Byte offset within the record.
     pub position: Option<usize>,
+    pub severity: Severity,
     pub message: String,
+    /// The offending value, and what it was replaced with when fixed.
+    pub original: Option<String>,
+    pub replacement: Option<String>,
     pub fixed: bool,
 }
 
+impl ValidationIssue {
+    pub fn new(validator: &str, line_number: usize, message: String) -> Self {
+        Self {
+            validator: validator.to_string(),
+            line_number,
+            message,
+            ..Default::default()
+        }
+    }
+}
+
+/// The outcome of validating a single row: the row as read, the row after fixing, and the issues found.
+/// Both rows borrow the input until a fix rewrites the row.
 #[derive(Debug, Clone)]
-pub struct ValidationResult {
-    pub line: String,
+pub struct ValidationResult<'a> {
+    pub original: Cow<'a, str>,
+    pub fixed: Cow<'a, str>,
+    pub modified: bool,
     pub issues: Vec<ValidationIssue>,
 }
 
-impl ValidationResult {
-    pub fn new(line: String) -> Self {
-        Self { line, issues: Vec::new() }
+impl<'a> ValidationResult<'a> {
+    pub fn new(line: impl Into<Cow<'a, str>>) -> Self {
+        let line = line.into();
+        Self {
+            original: line.clone(),
+            fixed: line,
+            modified: false,
+            issues: Vec::new(),
+        }
     }
 
     /// Append an issue
@@ -34,12 +154,13 @@ mod tests {
             position: Some(5),
             message: "Invalid field".to_string(),
             fixed: false,
+            ..Default::default()
         };
 
         assert_eq!(test_issue.line_number, 1);
 
         let test_result = ValidationResult::new("a,b,c".to_string());
-        assert_eq!(test_result.line, "a,b,c");
+        assert_eq!(test_result.fixed, "a,b,c");
 
         let test_result = test_result.add_issue(test_issue.clone());
         assert_eq!(test_result.issues.len(), 1);
@@ -47,16 +168,29 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_policy() {
+        assert!(Severity::Warning < Severity::Error);
+        assert_eq!("Warning".parse::<Severity>(), Ok(Severity::Warning));
+        assert_eq!("abort".parse::<ErrorPolicy>(), Ok(ErrorPolicy::Abort));
+        assert!("ignore".parse::<ErrorPolicy>().is_err());
+
+        let policy = Policy::default();
+        assert_eq!(policy.severity, Severity::Error);
+        assert_eq!(policy.on_error, ErrorPolicy::Drop);
+    }
+
     #[test]
     fn test_validation_result() {
         let test_result = ValidationResult::new("a,b,c".to_string());
-        assert_eq!(test_result.line, "a,b,c");
+        assert_eq!(test_result.fixed, "a,b,c");
 
         let test_issue = ValidationIssue {
             line_number: 1,
             position: Some(5),
             message: "Invalid field".to_string(),
             fixed: false,
+            ..Default::default()
         };
 
         let test_result = test_result.add_issue(test_issue.clone());
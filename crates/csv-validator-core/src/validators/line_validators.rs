@@ -1,148 +1,469 @@
-use crate::utils::csv_utils::line_processor;
-use crate::validators::issue::{ValidationIssue, ValidationResult};
+use std::borrow::Cow;
 
-// pub(crate) type Validator = dyn Fn(&str) -> Option<&str> + Sync;
-pub type Validator = dyn Fn(ValidationResult, usize) -> ValidationResult + Send + Sync;
+use aho_corasick::AhoCorasick;
+use serde::{Deserialize, Serialize};
 
-pub(crate) type Validators<'a> = &'a Vec<Box<Validator>>;
+use crate::config::CommonConfig;
+use crate::utils::tokenizer::Fields;
+use crate::validators::Validator;
+use crate::validators::issue::{ErrorPolicy, Policy, ValidationIssue, ValidationResult};
 
-/// Validator: validate the number of fields in a line of a CSV file.
-/// It will return the line if the number of fields is equal to the expected number.
-///
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IllegalCharsConfig {
+    pub illegal_chars: Vec<String>,
+    pub replace_with: Vec<String>,
+    pub common: CommonConfig,
+    #[serde(flatten)]
+    pub policy: Policy,
+}
+
+/// Validator: report (and optionally replace) illegal characters or substrings.
+#[derive(Clone)]
+pub struct IllegalChars {
+    cfg: IllegalCharsConfig,
+    pub matcher: AhoCorasick,
+}
+
+impl IllegalChars {
+    pub fn new(cfg: IllegalCharsConfig) -> Self {
+        let matcher = AhoCorasick::new(&cfg.illegal_chars).expect("Failed to build matcher");
+        Self { cfg, matcher }
+    }
+}
+
+// Aho-Corasick: faster for more patterns, but possibly slower for few patterns
+impl Validator for IllegalChars {
+    fn name(&self) -> &'static str {
+        "illegal_chars"
+    }
+
+    fn policy(&self) -> Policy {
+        self.cfg.policy
+    }
+
+    fn bind_dialect(&mut self, common: &CommonConfig) {
+        self.cfg.common.inherit(common);
+    }
+
+    fn validate<'a>(&self, input: &'a str, _fields: &Fields<'a>, row: usize) -> ValidationResult<'a> {
+        let fix = self.should_fix();
+        let mut fixed = Cow::Borrowed(input);
+        let mut modified = false;
+        let mut issues = Vec::new();
+
+        let mut pattern_matches: Vec<Vec<usize>> = vec![vec![]; self.cfg.illegal_chars.len()];
+        for mat in self.matcher.find_iter(input) {
+            pattern_matches[mat.pattern()].push(mat.start());
+        }
+
+        for (i, positions) in pattern_matches.into_iter().enumerate() {
+            if positions.is_empty() {
+                continue;
+            }
+
+            let pattern = &self.cfg.illegal_chars[i];
+            let rep = self.cfg.replace_with.get(i).cloned().unwrap_or_default();
+
+            for position in positions {
+                issues.push(ValidationIssue {
+                    position: Some(position),
+                    original: Some(pattern.clone()),
+                    replacement: fix.then(|| rep.clone()),
+                    fixed: fix,
+                    ..ValidationIssue::new(
+                        self.name(),
+                        row,
+                        format!("Illegal char |-> {} <-| found on row {} at position {}", pattern, row, position),
+                    )
+                });
+            }
+
+            if fix {
+                fixed = Cow::Owned(fixed.replace(pattern, &rep));
+                modified = true;
+            }
+        }
+
+        ValidationResult {
+            original: Cow::Borrowed(input),
+            fixed,
+            modified,
+            issues,
+        }
+    }
+
+    fn should_fix(&self) -> bool {
+        self.cfg.policy.on_error == ErrorPolicy::Fix
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FieldCountConfig {
+    pub expected: usize,
+    pub common: CommonConfig,
+    #[serde(flatten)]
+    pub policy: Policy,
+}
+
+/// Validator: validate the number of fields in a row.
+/// Separators inside quoted fields don't count. The issue points at the first extra field,
+/// or at the end of the row for the first missing field.
 ///
 /// # Example
 ///
 /// ```
-/// use csv_validator_core::validators::issue::ValidationResult;
-/// use csv_validator_core::validators::line_validators::validate_line_field_count;
+/// use csv_validator_core::config::CommonConfig;
+/// use csv_validator_core::validators::Validator;
+/// use csv_validator_core::validators::issue::Policy;
+/// use csv_validator_core::utils::tokenizer::Fields;
+/// use csv_validator_core::validators::line_validators::{FieldCount, FieldCountConfig};
+///
+/// let validator = FieldCount::new(FieldCountConfig {
+///     expected: 3,
+///     common: CommonConfig::default(),
+///     policy: Policy::default(),
+/// });
+///
+/// let issues = |row: &str, n| validator.validate(row, &Fields::new(row, ",", Some('"')), n).issues;
+/// assert!(issues("a,b,c", 1).is_empty());
 ///
-/// let line = "a,b,c";
-/// let validation_result = ValidationResult::new(line.to_string());
-/// let result = validate_line_field_count(validation_result, 3, &",".to_string(), Some('"'), 0);
-/// assert_eq!(result.issues, Vec::new());
+/// assert!(issues(r#"a,"b,c",d"#, 2).is_empty());
 ///
-/// let line = "a,b";
-/// let validation_result = ValidationResult::new(line.to_string());
-/// let result = validate_line_field_count(validation_result, 3, &",".to_string(), Some('"'), 0);
-/// assert_eq!(result.issues.len(), 1);
-/// assert!(result.issues[0].message.contains("Incorrect field count"));
+/// let issues = issues("a,b,c,d", 3);
+/// assert_eq!(issues[0].message, "Expected 3 fields on row 3, found 4: extra field at column 4, position 6");
+/// assert_eq!((issues[0].column, issues[0].position), (Some(3), Some(6)));
 /// ```
-pub fn validate_line_field_count<'a>(
-    input: ValidationResult,
-    num_fields: usize,
-    separator: &String,
-    quote_char: Option<char>,
-    line_number: usize,
-) -> ValidationResult {
-    let line = &input.line;
-    let fields_result = line_processor(line, separator, quote_char);
-    let fields = match fields_result {
-        Ok(fields) => fields,
-        Err(_) => {
-            let issue = ValidationIssue {
-                line_number,
-                position: None,
-                message: "Error parsing fields".to_string(),
-                fixed: false,
-            };
-            return ValidationResult {
-                line: line.to_string(),
-                issues: vec![issue],
+#[derive(Clone)]
+pub struct FieldCount {
+    cfg: FieldCountConfig,
+    header: Option<Vec<String>>,
+}
+
+impl FieldCount {
+    pub fn new(cfg: FieldCountConfig) -> Self {
+        Self { cfg, header: None }
+    }
+}
+
+impl Validator for FieldCount {
+    fn name(&self) -> &'static str {
+        "field_count"
+    }
+
+    fn policy(&self) -> Policy {
+        self.cfg.policy
+    }
+
+    fn bind_dialect(&mut self, common: &CommonConfig) {
+        self.cfg.common.inherit(common);
+    }
+
+    fn validate<'a>(&self, input: &'a str, fields: &Fields<'a>, row: usize) -> ValidationResult<'a> {
+        let sep = self.cfg.common.separator.as_deref().unwrap_or(",");
+        let fields = fields.split_as(sep, Some(self.cfg.common.quote_char()));
+        let (expected, actual) = (self.cfg.expected, fields.len());
+
+        let mut issues = Vec::new();
+        if actual != expected {
+            // the first extra field, or the first missing one (which would start at the end of the row)
+            let (column, position, what) = if actual > expected {
+                (expected, fields.ranges()[expected].start, "extra")
+            } else {
+                (actual, input.len(), "missing")
             };
+            let message = format!(
+                "Expected {} fields on row {}, found {}: {} field at column {}, position {}",
+                expected,
+                row,
+                actual,
+                what,
+                column + 1,
+                position
+            );
+            issues.push(ValidationIssue {
+                column: Some(column),
+                column_name: self.header.as_ref().and_then(|header| header.get(column).cloned()),
+                position: Some(position),
+                ..ValidationIssue::new(self.name(), row, message)
+            });
         }
-    };
-    if fields.len() > num_fields {
-        // “fix” it by trimming extra fields
-        let fixed_line = fields[..num_fields].join(&separator.to_string());
-        let issue = ValidationIssue {
-            line_number,
-            position: None,
-            message: format!(
-                "Incorrect field count: expected {}, got {}. Fixed by trimming.",
-                num_fields,
-                fields.len()
-            ),
-            fixed: true,
-        };
+
         ValidationResult {
-            line: fixed_line,
-            issues: {
-                let mut v = input.issues;
-                v.push(issue);
-                v
-            },
+            issues,
+            ..ValidationResult::new(input)
+        }
+    }
+
+    fn bind_header(&mut self, header: &[String]) {
+        self.header = Some(header.to_vec());
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SeparatorConfig {
+    pub common: CommonConfig,
+    #[serde(flatten)]
+    pub policy: Policy,
+}
+
+/// Validator: validate the presence of the separator in a row.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::config::CommonConfig;
+/// use csv_validator_core::validators::Validator;
+/// use csv_validator_core::validators::issue::Policy;
+/// use csv_validator_core::utils::tokenizer::Fields;
+/// use csv_validator_core::validators::line_validators::{Separator, SeparatorConfig};
+///
+/// let validator = Separator::new(SeparatorConfig {
+///     common: CommonConfig::default(),
+///     policy: Policy::default(),
+/// });
+///
+/// let issues = |row: &str| validator.validate(row, &Fields::new(row, ",", Some('"')), 1).issues;
+/// assert!(issues("a,b,c").is_empty());
+///
+/// let issues = issues("a;b;c");
+/// assert_eq!(issues.len(), 1);
+/// assert!(issues[0].message.contains("Expected separator"));
+/// ```
+#[derive(Clone)]
+pub struct Separator {
+    cfg: SeparatorConfig,
+}
+
+impl Separator {
+    pub fn new(cfg: SeparatorConfig) -> Self {
+        Self { cfg }
+    }
+}
+
+impl Validator for Separator {
+    fn name(&self) -> &'static str {
+        "separator"
+    }
+
+    fn policy(&self) -> Policy {
+        self.cfg.policy
+    }
+
+    fn bind_dialect(&mut self, common: &CommonConfig) {
+        self.cfg.common.inherit(common);
+    }
+
+    fn validate<'a>(&self, input: &'a str, _fields: &Fields<'a>, row: usize) -> ValidationResult<'a> {
+        // todo: check if the separator is present in the line and is in fact the separator
+        let sep = self.cfg.common.separator.as_deref().unwrap_or(",");
+
+        let mut issues = Vec::new();
+        if !input.contains(sep) {
+            let message = format!("Expected separator '{}' not found.", sep);
+            issues.push(ValidationIssue::new(self.name(), row, message));
         }
-    } else if fields.len() < num_fields {
-        let fixed_line = format!("{},{}", line, " ".repeat(num_fields - fields.len()));
-        let issue = ValidationIssue {
-            line_number,
-            position: None,
-            message: format!(
-                "Incorrect field count: expected {}, got {}.",
-                num_fields,
-                fields.len()
-            ),
-            fixed: true,
-        };
+
         ValidationResult {
-            line: fixed_line.to_string(),
-            issues: {
-                let mut v = input.issues;
-                v.push(issue);
-                v
-            },
+            issues,
+            ..ValidationResult::new(input)
         }
-    } else {
-        input
     }
+}
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Utf8Config {
+    /// Replace invalid sequences with this character instead of U+FFFD.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replace_with: Option<char>,
+    pub common: CommonConfig,
+    #[serde(flatten)]
+    pub policy: Policy,
 }
 
-/// Validator: validate the presence of a separator in a line of a CSV file.
-/// It will return the line if the separator is found.
-///
+/// Validator: report bytes that aren't valid UTF-8, with their byte offset in the row,
+/// and optionally replace them.
 ///
 /// # Example
 ///
 /// ```
-/// use csv_validator_core::validators::issue::ValidationResult;
-/// use csv_validator_core::validators::line_validators::validate_line_separator;
+/// use csv_validator_core::config::CommonConfig;
+/// use csv_validator_core::validators::Validator;
+/// use csv_validator_core::validators::issue::{ErrorPolicy, Policy};
+/// use csv_validator_core::validators::line_validators::{Utf8, Utf8Config};
 ///
-/// let line = "a,b,c";
-/// let validation_result = ValidationResult::new(line.to_string());
-/// let result = validate_line_separator(validation_result, ',', 0);
-/// assert_eq!(result.issues, Vec::new());
+/// let validator = Utf8::new(Utf8Config {
+///     replace_with: Some('?'),
+///     common: CommonConfig::default(),
+///     policy: Policy { on_error: ErrorPolicy::Fix, ..Policy::default() },
+/// });
 ///
-/// let line = "a;b;c";
-/// let validation_result = ValidationResult::new(line.to_string());
-/// let result = validate_line_separator(validation_result, ',', 0);
-/// assert_eq!(result.issues.len(), 1);
-/// dbg!(&result);
-/// assert!(result.issues[0].message.contains("Expected separator"));
+/// let result = validator.validate_bytes(b"1,Ren\xE9e", 2).unwrap();
+/// assert_eq!(result.fixed, "1,Ren?e");
+/// assert_eq!(result.issues[0].message, "Invalid UTF-8 \\xE9 on row 2 at byte 5");
 /// ```
-pub fn validate_line_separator(
-    input: ValidationResult,
-    expected_sep: char,
-    line_number: usize,
-) -> ValidationResult {
-    // todo: check if the separator is present in the line and is in fact the separator
-    if !input.line.contains(expected_sep) {
-        let issue = ValidationIssue {
-            line_number,
-            position: None,
-            message: format!("Expected separator '{}' not found.", expected_sep),
-            fixed: false,
-        };
-        input.add_issue(issue)
-    } else {
-        input
-    }
-}
-
-// pub fn validate_line_separator(line: &str, separator: char) -> Option<&str> {
-//     if line.contains(separator) {
-//         return Some(line);
-//     }
-//     println!("Separator not found");
-//     None
-// }
+#[derive(Clone)]
+pub struct Utf8 {
+    cfg: Utf8Config,
+}
+
+impl Utf8 {
+    pub fn new(cfg: Utf8Config) -> Self {
+        Self { cfg }
+    }
+}
+
+impl Validator for Utf8 {
+    fn name(&self) -> &'static str {
+        "utf8"
+    }
+
+    fn policy(&self) -> Policy {
+        self.cfg.policy
+    }
+
+    fn bind_dialect(&mut self, common: &CommonConfig) {
+        self.cfg.common.inherit(common);
+    }
+
+    /// Rows that get here are valid UTF-8.
+    fn validate<'a>(&self, input: &'a str, _fields: &Fields<'a>, _row: usize) -> ValidationResult<'a> {
+        ValidationResult::new(input)
+    }
+
+    fn validate_bytes(&self, input: &[u8], row: usize) -> Option<ValidationResult<'static>> {
+        let replacement = self.cfg.replace_with.unwrap_or(char::REPLACEMENT_CHARACTER);
+        let fix = self.should_fix();
+        let mut fixed = String::with_capacity(input.len());
+        let mut issues = Vec::new();
+        let mut position = 0;
+
+        for chunk in input.utf8_chunks() {
+            fixed.push_str(chunk.valid());
+            position += chunk.valid().len();
+
+            let invalid = chunk.invalid();
+            if invalid.is_empty() {
+                continue;
+            }
+            let bytes: String = invalid.iter().map(|b| format!("\\x{:02X}", b)).collect();
+            issues.push(ValidationIssue {
+                position: Some(position),
+                original: Some(bytes.clone()),
+                replacement: fix.then(|| replacement.to_string()),
+                fixed: fix,
+                ..ValidationIssue::new(
+                    self.name(),
+                    row,
+                    format!("Invalid UTF-8 {} on row {} at byte {}", bytes, row, position),
+                )
+            });
+            // unfixed rows keep the U+FFFD they were read with
+            fixed.push(if fix { replacement } else { char::REPLACEMENT_CHARACTER });
+            position += invalid.len();
+        }
+
+        Some(ValidationResult {
+            original: Cow::Owned(String::from_utf8_lossy(input).into_owned()),
+            modified: fix && !issues.is_empty(),
+            fixed: Cow::Owned(fixed),
+            issues,
+        })
+    }
+
+    fn should_fix(&self) -> bool {
+        self.cfg.policy.on_error == ErrorPolicy::Fix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn illegal_chars(on_error: ErrorPolicy) -> IllegalChars {
+        IllegalChars::new(IllegalCharsConfig {
+            illegal_chars: vec!["!".to_string(), "tv".to_string()],
+            replace_with: vec!["_".to_string()],
+            common: CommonConfig::default(),
+            policy: Policy { on_error, ..Policy::default() },
+        })
+    }
+
+    #[test]
+    fn test_illegal_chars() {
+        let row = "a!,tv!";
+        let fields = Fields::new(row, ",", Some('"'));
+        let result = illegal_chars(ErrorPolicy::Drop).validate(row, &fields, 3);
+        assert_eq!(result.fixed, "a!,tv!");
+        assert!(!result.modified);
+        assert_eq!(result.issues.len(), 3);
+        assert!(result.issues.iter().all(|issue| !issue.fixed && issue.line_number == 3));
+
+        // the `fix` policy fixes; patterns without a replacement are removed
+        let validator = illegal_chars(ErrorPolicy::Fix);
+        assert!(validator.should_fix());
+        let result = validator.validate(row, &fields, 3);
+        assert_eq!(result.fixed, "a_,_");
+        assert!(result.modified);
+        assert_eq!(result.issues[1].replacement.as_deref(), Some("_"));
+        assert!(result.issues.iter().all(|issue| issue.fixed));
+    }
+
+    #[test]
+    fn test_utf8() {
+        let validator = Utf8::new(Utf8Config {
+            replace_with: None,
+            common: CommonConfig::default(),
+            policy: Policy::default(),
+        });
+        // a lone continuation byte and a truncated sequence
+        let result = validator.validate_bytes(b"\x80a,\xE2\x82", 7).unwrap();
+        assert_eq!(result.fixed, "\u{fffd}a,\u{fffd}");
+        assert!(!result.modified);
+        let issues: Vec<_> = result.issues.iter().map(|i| (i.position, i.original.as_deref(), i.fixed)).collect();
+        assert_eq!(issues, [(Some(0), Some("\\x80"), false), (Some(3), Some("\\xE2\\x82"), false)]);
+
+        // the fix policy replaces with U+FFFD
+        let validator = Utf8::new(Utf8Config {
+            replace_with: None,
+            common: CommonConfig::default(),
+            policy: Policy { on_error: ErrorPolicy::Fix, ..Policy::default() },
+        });
+        let result = validator.validate_bytes(b"a\xFF", 1).unwrap();
+        assert!(result.modified && result.issues[0].fixed);
+        assert_eq!(result.issues[0].replacement.as_deref(), Some("\u{fffd}"));
+    }
+
+    #[test]
+    fn test_field_count_separator() {
+        let validator = FieldCount::new(FieldCountConfig {
+            expected: 2,
+            common: CommonConfig { separator: Some("$$".to_string()), ..CommonConfig::default() },
+            policy: Policy::default(),
+        });
+        // the rows are split with the separator of the validator, not with the one they come with
+        let issues = |row: &str| validator.validate(row, &Fields::new(row, ",", Some('"')), 1).issues;
+        assert!(issues("a$$b").is_empty());
+        assert_eq!(issues("a,b").len(), 1);
+    }
+
+    #[test]
+    fn test_field_count_quoted() {
+        let mut validator = FieldCount::new(FieldCountConfig {
+            expected: 3,
+            common: CommonConfig::default(),
+            policy: Policy::default(),
+        });
+        validator.bind_header(&["id", "colors", "name"].map(String::from));
+        let issues = |row: &str, n| validator.validate(row, &Fields::new(row, ",", Some('"')), n).issues;
+
+        assert!(issues(r#"1,"red,green,""light"" blue",x"#, 2).is_empty());
+
+        let issues_3 = issues(r#"1,"red,green""#, 3);
+        assert_eq!(issues_3[0].message, "Expected 3 fields on row 3, found 2: missing field at column 3, position 13");
+        assert_eq!(issues_3[0].column_name.as_deref(), Some("name"));
+
+        let issues_4 = issues(r#"1,red,green,"x,y""#, 4);
+        assert_eq!((issues_4[0].column, issues_4[0].position), (Some(3), Some(12)));
+        assert_eq!(issues_4[0].column_name, None);
+    }
+}
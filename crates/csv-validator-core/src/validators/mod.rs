@@ -1,2 +1,148 @@
 pub mod line_validators;
+pub mod file_validators;
 pub mod issue;
+pub mod column_validators;
+pub mod registry;
+
+use std::any::Any;
+
+use crate::config::CommonConfig;
+use crate::utils::tokenizer::Fields;
+use crate::validators::issue::{Policy, ValidationIssue, ValidationResult};
+
+/// A validator checks (and possibly fixes) one row at a time.
+/// Validators are shared between worker threads, so `validate` takes `&self`;
+/// file-level checks keep their state in an [`Accumulator`] instead.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::config::CommonConfig;
+/// use csv_validator_core::utils::tokenizer::Fields;
+/// use csv_validator_core::validators::Validator;
+/// use csv_validator_core::validators::issue::{ErrorPolicy, Policy};
+/// use csv_validator_core::validators::line_validators::{IllegalChars, IllegalCharsConfig};
+///
+/// let validator = IllegalChars::new(IllegalCharsConfig {
+///     illegal_chars: vec!["!".to_string()],
+///     replace_with: vec!["?".to_string()],
+///     common: CommonConfig::default(),
+///     policy: Policy { on_error: ErrorPolicy::Fix, ..Policy::default() },
+/// });
+///
+/// let row = "a,b!,c";
+/// let result = validator.validate(row, &Fields::new(row, ",", Some('"')), 1);
+/// assert_eq!(result.fixed, "a,b?,c");
+/// assert_eq!(result.issues[0].position, Some(3));
+/// ```
+pub trait Validator: Send + Sync + CloneValidator {
+    /// Name of the validator in reports, as used in the config file.
+    fn name(&self) -> &'static str;
+    /// Validate one row, given with its fields: the engine splits every row once, with the dialect of the
+    /// input, for all validators (see [`Fields::split_as`]). The result borrows the row unless a fix rewrites it.
+    fn validate<'a>(&self, input: &'a str, fields: &Fields<'a>, row: usize) -> ValidationResult<'a>;
+    /// Fresh state for file-level checks, or `None` for validators that only look at one row at a time.
+    fn accumulator(&self) -> Option<Box<dyn Accumulator>> {
+        None
+    }
+    /// Fresh state for checks that need the rows in input order, or `None` for validators without one.
+    fn row_filter(&self) -> Option<Box<dyn RowFilter>> {
+        None
+    }
+    /// A digest of a validated (and fixed) row for the row filter, like a hash of the row. It is computed
+    /// on the worker threads, so the filter, which gets every row on a single thread, only has to compare.
+    fn row_digest(&self, _input: &str, _fields: &Fields) -> Option<u128> {
+        None
+    }
+    /// Called once after the last row with the merged state of all batches; returns file-level issues.
+    fn finalize(&self, _acc: Option<Box<dyn Accumulator>>) -> Vec<ValidationIssue> {
+        Vec::new()
+    }
+    /// Whether the validator fixes rows: validators that have a fix do with the `fix` policy.
+    fn should_fix(&self) -> bool {
+        false
+    }
+    /// Severity of the issues reported, and what to do with rows that have issues.
+    fn policy(&self) -> Policy {
+        Policy::default()
+    }
+    /// Called with the header fields before any row is validated (only if the input has a header),
+    /// so validators can refer to columns by name.
+    fn bind_header(&mut self, _header: &[String]) {}
+    /// Called before anything else with the dialect of the input (configured or detected), for what the
+    /// `common` section of the validator leaves open. Validators are built once and cloned for every input:
+    /// each clone is bound to the dialect of its input.
+    fn bind_dialect(&mut self, _common: &CommonConfig) {}
+    /// Whether the state of the validator grows with the input (like the keys seen), so it should
+    /// get a share of the memory limit with `bind_mem_limit`.
+    fn uses_memory(&self) -> bool {
+        false
+    }
+    /// Called before any row is validated with the memory the state of the validator may take,
+    /// if it `uses_memory`.
+    fn bind_mem_limit(&mut self, _mem_limit_bytes: usize) {}
+    /// Called with the bytes of rows that aren't valid UTF-8, before `validate` gets the row with U+FFFD
+    /// in place of the invalid sequences. Returns `None` for validators that don't check bytes.
+    fn validate_bytes(&self, _input: &[u8], _row: usize) -> Option<ValidationResult<'static>> {
+        None
+    }
+}
+
+pub trait CloneValidator {
+    fn clone_box(&self) -> Box<dyn Validator>;
+}
+
+impl<T> CloneValidator for T
+where
+    T: Validator + Clone + 'static,
+{
+    fn clone_box(&self) -> Box<dyn Validator> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Validator> {
+    fn clone(&self) -> Box<dyn Validator> {
+        self.clone_box()
+    }
+}
+
+/// Per-batch state of a file-level validator (row counts, seen keys, checksums, ...).
+/// Every worker accumulates its rows into its own instance, so no locking is needed;
+/// instances are merged after each batch and handed to `Validator::finalize` at the end of the file.
+pub trait Accumulator: Send + IntoAny {
+    fn accumulate(&mut self, input: &str, fields: &Fields, row: usize);
+    /// Merge the state of another instance, created by the same validator, into this one.
+    fn merge(&mut self, other: Box<dyn Accumulator>);
+}
+
+/// State of a check that depends on the rows before, like the rows seen. Unlike an [`Accumulator`],
+/// a single instance gets all rows, in input order, once they have been validated (and fixed),
+/// on the thread that writes them.
+pub trait RowFilter: Send {
+    /// The issues of a row, given with its [`Validator::row_digest`]; a fixed issue leaves the row out of
+    /// the output and the rejects.
+    fn check(&mut self, input: &str, row: usize, digest: Option<u128>) -> Vec<ValidationIssue>;
+    /// Called after the last row: what is left of the filter goes to [`Validator::finalize`], in place of an
+    /// accumulator (a validator has one or the other).
+    fn finish(self: Box<Self>) -> Option<Box<dyn Accumulator>> {
+        None
+    }
+}
+
+pub trait IntoAny {
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Any> IntoAny for T {
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// Recover the concrete accumulator; validators only ever receive the instances they created.
+pub fn downcast_accumulator<T: Accumulator + 'static>(acc: Box<dyn Accumulator>) -> Box<T> {
+    acc.into_any()
+        .downcast::<T>()
+        .expect("accumulator of unexpected type")
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use serde_yaml::Value;
+
+use crate::validators::Validator;
+use crate::validators::column_validators::{ColumnTypes, ColumnTypesConfig, Header, HeaderConfig};
+use crate::validators::file_validators::{
+    DuplicateRows, DuplicateRowsConfig, LineCount, LineCountConfig, Unique, UniqueConfig,
+};
+use crate::validators::line_validators::{
+    FieldCount, FieldCountConfig, IllegalChars, IllegalCharsConfig, Separator, SeparatorConfig, Utf8,
+    Utf8Config,
+};
+
+/// Builds a validator from its (yaml) config.
+pub type ValidatorFactory = Box<dyn Fn(Value) -> Result<Box<dyn Validator>, serde_yaml::Error> + Send + Sync>;
+
+/// The built-in validators by name, as used in the `type` of a config file entry.
+/// Embedders can add their own validators to the registry.
+///
+/// # Example
+///
+/// ```
+/// use csv_validator_core::utils::tokenizer::Fields;
+/// use csv_validator_core::validators::registry::build_registry;
+///
+/// let registry = build_registry();
+/// let args = serde_yaml::from_str(r#"
+/// expected: 3
+/// common:
+///   quote_char: '"'
+///   separator: ';'
+///   has_header: false
+/// "#).unwrap();
+///
+/// let validator = registry["field_count"](args).unwrap();
+/// assert_eq!(validator.name(), "field_count");
+/// assert_eq!(validator.validate("a;b", &Fields::new("a;b", ";", Some('"')), 1).issues.len(), 1);
+/// ```
+pub fn build_registry() -> HashMap<String, ValidatorFactory> {
+    let mut reg: HashMap<String, ValidatorFactory> = HashMap::new();
+
+    reg.insert("illegal_chars".into(), Box::new(|args| {
+        let cfg: IllegalCharsConfig = serde_yaml::from_value(args)?;
+        Ok(Box::new(IllegalChars::new(cfg)))
+    }));
+
+    reg.insert("utf8".into(), Box::new(|args| {
+        let cfg: Utf8Config = serde_yaml::from_value(args)?;
+        Ok(Box::new(Utf8::new(cfg)))
+    }));
+
+    reg.insert("field_count".into(), Box::new(|args| {
+        let cfg: FieldCountConfig = serde_yaml::from_value(args)?;
+        Ok(Box::new(FieldCount::new(cfg)))
+    }));
+
+    reg.insert("separator".into(), Box::new(|args| {
+        let cfg: SeparatorConfig = serde_yaml::from_value(args)?;
+        Ok(Box::new(Separator::new(cfg)))
+    }));
+
+    reg.insert("line_count".into(), Box::new(|args| {
+        let cfg: LineCountConfig = serde_yaml::from_value(args)?;
+        Ok(Box::new(LineCount::new(cfg)))
+    }));
+
+    reg.insert("column_types".into(), Box::new(|args| {
+        let cfg: ColumnTypesConfig = serde_yaml::from_value(args)?;
+        Ok(Box::new(ColumnTypes::new(cfg)))
+    }));
+
+    reg.insert("header".into(), Box::new(|args| {
+        let cfg: HeaderConfig = serde_yaml::from_value(args)?;
+        Ok(Box::new(Header::new(cfg)))
+    }));
+
+    reg.insert("unique".into(), Box::new(|args| {
+        let cfg: UniqueConfig = serde_yaml::from_value(args)?;
+        Ok(Box::new(Unique::new(cfg)))
+    }));
+
+    reg.insert("duplicate_rows".into(), Box::new(|args| {
+        let cfg: DuplicateRowsConfig = serde_yaml::from_value(args)?;
+        Ok(Box::new(DuplicateRows::new(cfg)))
+    }));
+
+    reg
+}
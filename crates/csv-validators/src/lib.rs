@@ -1,14 +1,32 @@
+use csv_validator_core::config::load_config;
+use csv_validator_core::validate_file;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use pyo3::types::{PyModule};
 
 #[pyfunction]
-pub fn jahallo(py: Python) -> PyResult<String>{
+pub fn jahallo(_py: Python) -> PyResult<String>{
     Ok("jahallo".to_string())
 }
 
+/// Validate a csv file with the validators from a yaml config file; returns the issue messages.
+#[pyfunction]
+pub fn validate(csv_filename: &str, config_filename: &str) -> PyResult<Vec<String>> {
+    let run = || -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let config = load_config(config_filename)?;
+        let common = config.common.clone();
+        let mut validators = config.build_validators()?;
+        let mut summary = validate_file(csv_filename, &mut validators, &common)?;
+        let messages = summary.issues.issues()?.map(|issue| issue.map(|issue| issue.message));
+        Ok(messages.collect::<std::io::Result<_>>()?)
+    };
+    run().map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
 // main entrypoint for python module
 #[pymodule]
 fn csv_validators(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(jahallo, m)?)?;
+    m.add_function(wrap_pyfunction!(validate, m)?)?;
     Ok(())
-}
\ No newline at end of file
+}